@@ -0,0 +1,31 @@
+//! Feeds arbitrary bytes through the frame decoder in arbitrarily sized chunks, then tries to parse whatever frame comes out.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use miku_codec::{decode_response, FrameDecoder, FRAME_DELIMITER};
+use miniserde_miku::json::Value;
+
+fuzz_target!(|data: &[u8]| {
+    let (chunk_len, bytes) = match data.split_first() {
+        Some((&len, bytes)) => (len as usize % 64 + 1, bytes),
+        None => return,
+    };
+
+    let mut decoder = FrameDecoder::with_capacity(0);
+    let mut received = None;
+    for chunk in bytes.chunks(chunk_len) {
+        if let Some(frame) = decoder.feed(chunk) {
+            assert!(!frame.contains(&FRAME_DELIMITER));
+            received = Some(frame.to_vec());
+        }
+    }
+
+    if let Some(frame) = received {
+        // once complete, a frame must not change until the decoder is cleared.
+        assert_eq!(decoder.feed(bytes), Some(&frame[..]));
+        let _ = decode_response::<Value>(&frame);
+    }
+
+    decoder.clear();
+    assert!(decoder.frame().is_none());
+});
@@ -0,0 +1,42 @@
+//! Parses arbitrary frame payloads as responses to the method return types used by the wrappers, through both the miniserde and the borrowed decoders.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use miku_codec::{decode_borrowed, decode_response, decode_result, LazySeq, RawValue};
+use miku_rpc::types::{DeviceData, DeviceDataRef, ImportFileInfo, RobotActionResult};
+use miniserde_miku::json::Value;
+use std::borrow::Cow;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_response::<()>(data);
+    let _ = decode_response::<Option<()>>(data);
+    let _ = decode_response::<bool>(data);
+    let _ = decode_response::<i32>(data);
+    let _ = decode_response::<f64>(data);
+    let _ = decode_response::<String>(data);
+    let _ = decode_response::<Vec<String>>(data);
+    let _ = decode_response::<Option<Vec<u8>>>(data);
+    let _ = decode_response::<Option<ImportFileInfo>>(data);
+    let _ = decode_response::<Vec<DeviceData>>(data);
+    let _ = decode_response::<RobotActionResult>(data);
+    let _ = decode_response::<Value>(data);
+    let _ = decode_result::<Value>(data);
+
+    let _ = decode_borrowed::<Option<()>>(data);
+    let _ = decode_borrowed::<i32>(data);
+    let _ = decode_borrowed::<Cow<str>>(data);
+    let _ = decode_borrowed::<Option<Vec<u8>>>(data);
+    if let Ok(raw) = decode_borrowed::<RawValue>(data) {
+        let _ = raw.data.entries().map(|entries| entries.count());
+
+        let mut bytes = Vec::new();
+        if raw.data.bytes_into(&mut bytes).is_err() {
+            assert!(bytes.is_empty());
+        }
+    }
+    if let Ok(devices) = decode_borrowed::<LazySeq<DeviceDataRef>>(data) {
+        for device in devices.data.iter().flatten() {
+            let _ = device.type_names.iter().count();
+        }
+    }
+});
@@ -0,0 +1,20 @@
+//! Parses arbitrary strings as SNBT and as item filters, checking that parsed tags survive being written back out.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use miku_rpc::filter::ItemFilter;
+use miku_rpc::nbt::Tag;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(tag) = Tag::parse(s) {
+        assert_eq!(Tag::parse(&tag.to_string()), Ok(tag));
+    }
+
+    if let Ok(filter) = s.parse::<ItemFilter>() {
+        let _ = filter.matches_tag("minecraft:stone", &Tag::default());
+    }
+});
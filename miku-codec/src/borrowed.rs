@@ -0,0 +1,728 @@
+use crate::{CodecError, MessageType, RPCError, Response, ResultValue};
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::str;
+
+/// A type that can be decoded straight out of a frame, borrowing from it where possible instead of allocating.
+pub trait DecodeBorrowed<'de>: Sized {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError>;
+
+    /// The value to use when a response has no `data` field at all, like [miniserde_miku::Deserialize::default].
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+/// Decodes the payload of a response frame without copying it; see [crate::decode_response].
+pub fn decode_borrowed<'de, R: DecodeBorrowed<'de>>(
+    frame: &'de [u8],
+) -> Result<Response<R>, CodecError> {
+    let Response { msg_type, data } = decode_borrowed_result(frame)?;
+
+    Ok(Response {
+        msg_type,
+        data: match data {
+            ResultValue::Void => R::missing().ok_or(CodecError::Malformed)?,
+            ResultValue::Null => R::decode(RawValue::NULL)?,
+            ResultValue::Value(v) => v,
+        },
+    })
+}
+
+/// Decodes the payload of a response frame without copying it; see [crate::decode_result].
+pub fn decode_borrowed_result<'de, R: DecodeBorrowed<'de>>(
+    frame: &'de [u8],
+) -> Result<Response<ResultValue<R>>, CodecError> {
+    let frame = str::from_utf8(frame).map_err(|_| CodecError::InvalidUtf8)?;
+
+    let mut msg_type = None;
+    let mut data = None;
+    let mut entries = RawValue(frame).entries()?;
+    for entry in &mut entries {
+        match entry? {
+            ("type", v) => msg_type = Some(v.decode::<MessageType>()?),
+            ("data", v) => data = Some(v),
+            _ => {}
+        }
+    }
+    // reading the entries straight off the frame saves skipping over it twice, but leaves the end of it to check here.
+    entries.reader.eat(b'}')?;
+    if entries.reader.peek().is_some() {
+        return Err(CodecError::Malformed);
+    }
+
+    match msg_type.ok_or(CodecError::Malformed)? {
+        MessageType::Error => Err(CodecError::Rpc(
+            data.ok_or(CodecError::Malformed)?.decode::<RPCError>()?,
+        )),
+        msg_type => Ok(Response {
+            msg_type,
+            data: match data {
+                None => ResultValue::Void,
+                Some(v) if v.is_null() => ResultValue::Null,
+                Some(v) => ResultValue::Value(R::decode(v)?),
+            },
+        }),
+    }
+}
+
+/// A json value inside a frame, left unparsed until it is decoded.
+#[derive(Copy, Clone, Debug)]
+pub struct RawValue<'de>(&'de str);
+
+impl<'de> RawValue<'de> {
+    const NULL: RawValue<'static> = RawValue("null");
+
+    /// Returns the json text of this value.
+    pub fn as_str(&self) -> &'de str {
+        self.0
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == "null"
+    }
+
+    pub fn decode<T: DecodeBorrowed<'de>>(self) -> Result<T, CodecError> {
+        T::decode(self)
+    }
+
+    /// Returns the contents of a string value, as long as it has no escape sequences that would need to be copied out.
+    pub fn as_plain_str(&self) -> Option<&'de str> {
+        let s = self.0.strip_prefix('"')?.strip_suffix('"')?;
+        if s.contains('\\') {
+            None
+        } else {
+            Some(s)
+        }
+    }
+
+    /// Iterates over the elements of an array value.
+    pub fn elements(self) -> Result<Elements<'de>, CodecError> {
+        let mut reader = Reader::new(self.0);
+        reader.eat(b'[')?;
+
+        Ok(Elements {
+            reader,
+            first: true,
+            done: false,
+        })
+    }
+
+    /// Iterates over the keys and values of an object value. Keys are returned as they appear in the frame, escape sequences included.
+    pub fn entries(self) -> Result<Entries<'de>, CodecError> {
+        let mut reader = Reader::new(self.0);
+        reader.eat(b'{')?;
+
+        Ok(Entries {
+            reader,
+            first: true,
+            done: false,
+        })
+    }
+
+    /// Parses an array of numbers straight into bytes, appending them to `out`. Numbers from -128 to -1 are taken to be java's signed bytes and wrap around to 128 to 255.
+    ///
+    /// On error, `out` is left the way it was.
+    pub fn bytes_into(self, out: &mut Vec<u8>) -> Result<(), CodecError> {
+        let len = out.len();
+        let res = parse_bytes(self.0.as_bytes(), out);
+        if res.is_err() {
+            out.truncate(len);
+        }
+
+        res
+    }
+}
+
+fn parse_bytes(src: &[u8], out: &mut Vec<u8>) -> Result<(), CodecError> {
+    let src = src
+        .strip_prefix(b"[")
+        .and_then(|s| s.strip_suffix(b"]"))
+        .ok_or(CodecError::Malformed)?;
+    let skip_whitespace = |pos: &mut usize| {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = src.get(*pos) {
+            *pos += 1;
+        }
+    };
+
+    // every element takes at least two characters, counting its comma.
+    out.reserve(src.len() / 2 + 1);
+
+    let mut pos = 0;
+    skip_whitespace(&mut pos);
+    if pos == src.len() {
+        return Ok(());
+    }
+
+    loop {
+        let negative = src.get(pos) == Some(&b'-');
+        if negative {
+            pos += 1;
+        }
+
+        let start = pos;
+        let mut n: u32 = 0;
+        while let Some(&b @ b'0'..=b'9') = src.get(pos) {
+            if pos - start == 3 {
+                return Err(CodecError::Malformed);
+            }
+            n = n * 10 + (b - b'0') as u32;
+            pos += 1;
+        }
+
+        out.push(match (negative, n) {
+            _ if pos == start => return Err(CodecError::Malformed),
+            (false, 0..=255) => n as u8,
+            (true, 0..=128) => (n as u8).wrapping_neg(),
+            _ => return Err(CodecError::Malformed),
+        });
+
+        skip_whitespace(&mut pos);
+        match src.get(pos) {
+            None => return Ok(()),
+            Some(b',') => {
+                pos += 1;
+                skip_whitespace(&mut pos);
+            }
+            Some(_) => return Err(CodecError::Malformed),
+        }
+    }
+}
+
+/// An iterator over the elements of an array, see [RawValue::elements].
+pub struct Elements<'de> {
+    reader: Reader<'de>,
+    first: bool,
+    done: bool,
+}
+
+impl<'de> Elements<'de> {
+    fn next_element(&mut self) -> Result<Option<RawValue<'de>>, CodecError> {
+        if self.reader.peek() == Some(b']') {
+            return Ok(None);
+        }
+
+        if !self.first {
+            self.reader.eat(b',')?;
+        }
+        self.first = false;
+
+        self.reader.value().map(Some)
+    }
+}
+
+impl<'de> Iterator for Elements<'de> {
+    type Item = Result<RawValue<'de>, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.next_element().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+/// An iterator over the entries of an object, see [RawValue::entries].
+pub struct Entries<'de> {
+    reader: Reader<'de>,
+    first: bool,
+    done: bool,
+}
+
+impl<'de> Entries<'de> {
+    fn next_entry(&mut self) -> Result<Option<(&'de str, RawValue<'de>)>, CodecError> {
+        if self.reader.peek() == Some(b'}') {
+            return Ok(None);
+        }
+
+        if !self.first {
+            self.reader.eat(b',')?;
+        }
+        self.first = false;
+
+        let key = self.reader.string()?;
+        self.reader.eat(b':')?;
+        Ok(Some((key, self.reader.value()?)))
+    }
+}
+
+impl<'de> Iterator for Entries<'de> {
+    type Item = Result<(&'de str, RawValue<'de>), CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.next_entry().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+/// A json array whose elements are only decoded as they are iterated over.
+pub struct LazySeq<'de, T> {
+    raw: RawValue<'de>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T: DecodeBorrowed<'de> + 'de> LazySeq<'de, T> {
+    pub fn iter(&self) -> impl Iterator<Item = Result<T, CodecError>> + 'de {
+        // the array was checked when this was decoded, so this can't fail.
+        self.raw
+            .elements()
+            .into_iter()
+            .flatten()
+            .map(|v| v.and_then(T::decode))
+    }
+
+    pub fn raw(&self) -> RawValue<'de> {
+        self.raw
+    }
+}
+
+impl<'de, T> Clone for LazySeq<'de, T> {
+    fn clone(&self) -> Self {
+        LazySeq {
+            raw: self.raw,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T> DecodeBorrowed<'de> for LazySeq<'de, T> {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        if !value.0.starts_with('[') {
+            return Err(CodecError::Malformed);
+        }
+
+        Ok(LazySeq {
+            raw: value,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for RawValue<'de> {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        Ok(value)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for () {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        if value.is_null() {
+            Ok(())
+        } else {
+            Err(CodecError::Malformed)
+        }
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for bool {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        match value.0 {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(CodecError::Malformed),
+        }
+    }
+}
+
+macro_rules! decode_number {
+    ($($t:ty)*) => {$(
+        impl<'de> DecodeBorrowed<'de> for $t {
+            fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+                value.0.parse().map_err(|_| CodecError::Malformed)
+            }
+        }
+    )*};
+}
+
+decode_number!(i8 i16 i32 i64 u8 u16 u32 u64 f32 f64);
+
+impl<'de> DecodeBorrowed<'de> for &'de str {
+    /// Only succeeds for strings without escape sequences; use `Cow<str>` for strings that might have them.
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        value.as_plain_str().ok_or(CodecError::Malformed)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for Cow<'de, str> {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        if let Some(s) = value.as_plain_str() {
+            return Ok(Cow::Borrowed(s));
+        }
+
+        let s = value
+            .0
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .ok_or(CodecError::Malformed)?;
+        unescape(s).map(Cow::Owned)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for String {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        Cow::<str>::decode(value).map(Cow::into_owned)
+    }
+}
+
+impl<'de, T: DecodeBorrowed<'de>> DecodeBorrowed<'de> for Option<T> {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            T::decode(value).map(Some)
+        }
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<'de, T: DecodeBorrowed<'de>> DecodeBorrowed<'de> for Vec<T> {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        value.elements()?.map(|v| v.and_then(T::decode)).collect()
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for MessageType {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        value
+            .as_plain_str()
+            .ok_or(CodecError::Malformed)?
+            .parse()
+            .map_err(|_| CodecError::Malformed)
+    }
+}
+
+impl<'de> DecodeBorrowed<'de> for RPCError {
+    fn decode(value: RawValue<'de>) -> Result<Self, CodecError> {
+        let Ok(error) = Cow::<str>::decode(value)?.parse();
+        Ok(error)
+    }
+}
+
+fn unescape(s: &str) -> Result<String, CodecError> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        out.push(match chars.next().ok_or(CodecError::Malformed)? {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = hex_escape(&mut chars)?;
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    if chars.next() != Some('\\') || chars.next() != Some('u') {
+                        return Err(CodecError::Malformed);
+                    }
+                    let low = hex_escape(&mut chars)?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(CodecError::Malformed);
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                char::from_u32(code).ok_or(CodecError::Malformed)?
+            }
+            _ => return Err(CodecError::Malformed),
+        });
+    }
+
+    Ok(out)
+}
+
+fn hex_escape(chars: &mut str::Chars<'_>) -> Result<u32, CodecError> {
+    (0..4).try_fold(0, |acc, _| {
+        let digit = chars
+            .next()
+            .and_then(|c| c.to_digit(16))
+            .ok_or(CodecError::Malformed)?;
+        Ok(acc << 4 | digit)
+    })
+}
+
+struct Reader<'de> {
+    src: &'de str,
+    pos: usize,
+}
+
+impl<'de> Reader<'de> {
+    fn new(src: &'de str) -> Reader<'de> {
+        Reader { src, pos: 0 }
+    }
+
+    fn byte(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.byte() {
+            self.pos += 1;
+        }
+        self.byte()
+    }
+
+    fn eat(&mut self, expected: u8) -> Result<(), CodecError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(CodecError::Malformed)
+        }
+    }
+
+    /// Reads a string, returning its contents between the quotes.
+    fn string(&mut self) -> Result<&'de str, CodecError> {
+        self.eat(b'"')?;
+        let start = self.pos;
+
+        loop {
+            let rest = &self.src.as_bytes()[self.pos..];
+            self.pos += rest
+                .iter()
+                .position(|b| matches!(b, b'"' | b'\\'))
+                .ok_or(CodecError::Malformed)?;
+            match self.byte() {
+                Some(b'"') => break,
+                // a backslash needs something after it to escape.
+                _ if self.pos + 1 >= self.src.len() => return Err(CodecError::Malformed),
+                _ => self.pos += 2,
+            }
+        }
+
+        // both ends sit next to an ascii quote, so they're always on a char boundary.
+        let s = &self.src[start..self.pos];
+        self.pos += 1;
+        Ok(s)
+    }
+
+    /// Skips over the next value, returning its text. Nested values are only checked for balanced brackets; their contents are checked when they are decoded.
+    fn value(&mut self) -> Result<RawValue<'de>, CodecError> {
+        let start = match self.peek() {
+            Some(_) => self.pos,
+            None => return Err(CodecError::Malformed),
+        };
+
+        match self.byte() {
+            Some(b'"') => {
+                self.string()?;
+            }
+            Some(b'{' | b'[') => self.skip_nested()?,
+            _ => {
+                while !matches!(
+                    self.byte(),
+                    None | Some(b',' | b']' | b'}' | b':' | b' ' | b'\t' | b'\n' | b'\r')
+                ) {
+                    self.pos += 1;
+                }
+
+                if self.pos == start {
+                    return Err(CodecError::Malformed);
+                }
+            }
+        }
+
+        Ok(RawValue(&self.src[start..self.pos]))
+    }
+
+    fn skip_nested(&mut self) -> Result<(), CodecError> {
+        let mut depth = 0usize;
+
+        loop {
+            match self.byte() {
+                Some(b'{' | b'[') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(b'}' | b']') => {
+                    depth -= 1;
+                    self.pos += 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(b'"') => {
+                    self.string()?;
+                }
+                Some(_) => {
+                    // jump straight to the next bracket or string, which makes long arrays of numbers cheap to skip.
+                    let rest = &self.src.as_bytes()[self.pos..];
+                    self.pos += rest
+                        .iter()
+                        .position(|b| matches!(b, b'{' | b'[' | b'}' | b']' | b'"'))
+                        .unwrap_or(rest.len());
+                }
+                None => return Err(CodecError::Malformed),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrows_plain_strings() {
+        let frame = br#"{"type":"result","data":"SUCCESS"}"#;
+        let data = decode_borrowed::<Cow<str>>(frame).unwrap().data;
+        assert!(matches!(data, Cow::Borrowed("SUCCESS")));
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let frame = br#"{"type":"result","data":"a\"b\\c\u00e9\ud83d\ude00"}"#;
+        let data = decode_borrowed::<Cow<str>>(frame).unwrap().data;
+        assert!(matches!(data, Cow::Owned(_)));
+        assert_eq!(data, "a\"b\\c\u{e9}\u{1f600}");
+        assert!(decode_borrowed::<&str>(frame).is_err());
+    }
+
+    #[test]
+    fn void_and_null() {
+        let void = br#"{"type":"result"}"#;
+        let null = br#"{"type":"result","data":null}"#;
+        assert_eq!(
+            decode_borrowed_result::<i32>(void).unwrap().data,
+            ResultValue::Void
+        );
+        assert_eq!(
+            decode_borrowed_result::<i32>(null).unwrap().data,
+            ResultValue::Null
+        );
+        assert_eq!(decode_borrowed::<Option<i32>>(void).unwrap().data, None);
+        assert!(decode_borrowed::<i32>(void).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        for frame in [
+            &br#"{"type":"result","data":1"#[..],
+            br#"{"type":"result","data":1}}"#,
+            br#"{"type":"result","data":1} x"#,
+            br#"{"type":"result","data":[1,2}"#,
+            br#"{"type":"result""data":1}"#,
+            br#"{"data":1}"#,
+        ] {
+            assert!(decode_borrowed::<RawValue>(frame).is_err(), "{:?}", frame);
+        }
+        assert!(decode_borrowed::<RawValue>(b" {\"type\":\"result\"} \n").is_err());
+        assert!(decode_borrowed::<Option<RawValue>>(b" {\"type\":\"result\"} \n").is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_strings() {
+        for frame in [
+            &br#"{"a\"#[..],
+            br#"{"type":"result","data":"\"#,
+            br#"{"type":"result","data":"\""#,
+            br#"{"type":"result","data":"abc"#,
+            br#"{"type":"result","data":["\"#,
+            br#"{"type":"result","data":{"\"#,
+        ] {
+            assert!(decode_borrowed::<RawValue>(frame).is_err(), "{:?}", frame);
+            assert!(decode_borrowed::<Cow<str>>(frame).is_err(), "{:?}", frame);
+        }
+    }
+
+    #[test]
+    fn error_responses() {
+        let frame = br#"{"type":"error","data":"unknown method"}"#;
+        assert!(matches!(
+            decode_borrowed::<RawValue>(frame),
+            Err(CodecError::Rpc(RPCError::UnknownMethod))
+        ));
+    }
+
+    #[test]
+    fn raw_values_keep_their_text() {
+        let frame = br#"{"type":"result","data": {"a": [1, {"b": "]"}], "c": null} }"#;
+        let data = decode_borrowed::<RawValue>(frame).unwrap().data;
+        assert_eq!(data.as_str(), r#"{"a": [1, {"b": "]"}], "c": null}"#);
+
+        let entries = data
+            .entries()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "a");
+        assert_eq!(entries[0].1.as_str(), r#"[1, {"b": "]"}]"#);
+        assert!(entries[1].1.is_null());
+    }
+
+    #[test]
+    fn lazy_seq_decodes_as_it_goes() {
+        let frame = br#"{"type":"result","data":[1, 2, "three", 4]}"#;
+        let seq = decode_borrowed::<LazySeq<i32>>(frame).unwrap().data;
+        let mut iter = seq.iter();
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        assert_eq!(iter.next().unwrap().unwrap(), 2);
+        // an element of the wrong type doesn't stop the ones after it from being decoded.
+        assert!(iter.next().unwrap().is_err());
+        assert_eq!(iter.next().unwrap().unwrap(), 4);
+        assert!(iter.next().is_none());
+
+        assert!(decode_borrowed::<LazySeq<i32>>(br#"{"type":"result","data":1}"#).is_err());
+        let empty = decode_borrowed::<LazySeq<i32>>(br#"{"type":"result","data":[ ]}"#).unwrap();
+        assert_eq!(empty.data.iter().count(), 0);
+    }
+
+    #[test]
+    fn vec_stops_at_the_first_error() {
+        let frame = br#"{"type":"result","data":[true,false]}"#;
+        assert_eq!(
+            decode_borrowed::<Vec<bool>>(frame).unwrap().data,
+            [true, false]
+        );
+        let frame = br#"{"type":"result","data":[true,1]}"#;
+        assert!(decode_borrowed::<Vec<bool>>(frame).is_err());
+    }
+
+    fn bytes(json: &str) -> Result<Vec<u8>, CodecError> {
+        let mut out = alloc::vec![9];
+        let res = RawValue(json).bytes_into(&mut out);
+        if res.is_err() {
+            assert_eq!(out, [9], "{:?} left bytes behind", json);
+        }
+        res.map(|()| out.split_off(1))
+    }
+
+    #[test]
+    fn byte_arrays() {
+        assert_eq!(bytes("[]").unwrap(), []);
+        assert_eq!(bytes("[ ]").unwrap(), []);
+        assert_eq!(bytes("[0,1,255]").unwrap(), [0, 1, 255]);
+        assert_eq!(bytes("[ 10 ,\n200\t]").unwrap(), [10, 200]);
+        assert_eq!(bytes("[-1,-128,-0]").unwrap(), [255, 128, 0]);
+
+        for json in [
+            "", "[", "1", "[1", "[256]", "[1000]", "[-129]", "[-]", "[1,]", "[,1]", "[1 2]",
+            "[1.5]", "[\"1\"]", "[null]",
+        ] {
+            assert!(bytes(json).is_err(), "{:?}", json);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{CodecError, MessageType, FRAME_DELIMITER};
+use arrayvec::ArrayString;
+use miniserde_miku::{json, Serialize};
+
+/// The largest message the HLApi accepts, frame delimiters included.
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+#[derive(Serialize)]
+/// A HLApi call, composed of a type and some json serializable data.
+pub struct Call<T: Serialize> {
+    #[serde(rename = "type")]
+    pub msg_type: MessageType,
+    pub data: T,
+}
+
+impl Call<()> {
+    pub fn list() -> Call<()> {
+        Call {
+            msg_type: MessageType::List,
+            data: (),
+        }
+    }
+}
+
+impl Call<&str> {
+    pub fn methods(device_id: &str) -> Call<&str> {
+        Call {
+            msg_type: MessageType::Methods,
+            data: device_id,
+        }
+    }
+}
+
+#[derive(Serialize)]
+/// A HLApi call that invokes a method on a specific device.
+pub struct InvokeCall<'a> {
+    #[serde(rename = "deviceId")]
+    device_id: &'a str,
+    #[serde(rename = "name")]
+    method_name: &'a str,
+    parameters: &'a [&'a dyn Serialize],
+}
+
+impl Call<InvokeCall<'_>> {
+    pub fn invoke<'a>(
+        device_id: &'a str,
+        method_name: &'a str,
+        parameters: &'a [&'a dyn Serialize],
+    ) -> Call<InvokeCall<'a>> {
+        Call {
+            msg_type: MessageType::Invoke,
+            data: InvokeCall {
+                device_id,
+                method_name,
+                parameters,
+            },
+        }
+    }
+}
+
+/// Serializes a call into `out` as a complete frame, delimiters included. Any previous contents of `out` are discarded.
+pub fn encode_call<T: Serialize>(
+    msg: &Call<T>,
+    out: &mut ArrayString<MAX_MESSAGE_SIZE>,
+) -> Result<(), CodecError> {
+    out.clear();
+    out.push(FRAME_DELIMITER as char);
+    json::to_string::<_, MAX_MESSAGE_SIZE, 16384>(msg, out);
+    out.try_push(FRAME_DELIMITER as char)
+        .map_err(|_| CodecError::MessageTooLarge)
+}
@@ -0,0 +1,359 @@
+use crate::FRAME_DELIMITER;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// A method parameter that writes itself as json straight into a frame, without going through miniserde.
+pub trait EncodeArg {
+    fn encode_arg(&self, out: &mut Vec<u8>);
+
+    /// Writes a slice of these as a json array. Types that show up in large arrays can override this with something faster than going element by element.
+    fn encode_slice(items: &[Self], out: &mut Vec<u8>)
+    where
+        Self: Sized,
+    {
+        out.push(b'[');
+        for (i, v) in items.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            v.encode_arg(out);
+        }
+        out.push(b']');
+    }
+}
+
+/// Writes an invoke call into a buffer, one argument at a time.
+///
+/// ```
+/// # use miku_codec::InvokeWriter;
+/// let mut frame = Vec::new();
+/// let mut call = InvokeWriter::new(&mut frame, "setRedstoneOutput");
+/// call.arg("north").arg(&15);
+/// call.finish("5c3c8d36-2b0e-4d1e-8e5b-6f8f3b7a9c21");
+/// ```
+pub struct InvokeWriter<'a> {
+    out: &'a mut Vec<u8>,
+    first: bool,
+}
+
+impl<'a> InvokeWriter<'a> {
+    /// Starts a frame invoking the method with the given name.
+    pub fn new(out: &'a mut Vec<u8>, method_name: &str) -> InvokeWriter<'a> {
+        out.push(FRAME_DELIMITER);
+        out.extend_from_slice(br#"{"type":"invoke","data":{"name":"#);
+        method_name.encode_arg(out);
+        out.extend_from_slice(br#","parameters":["#);
+
+        InvokeWriter { out, first: true }
+    }
+
+    /// Starts a frame from a header rendered ahead of time, up to and including the opening bracket of the parameter list; see [invoke_header].
+    pub fn with_header(out: &'a mut Vec<u8>, header: &[u8]) -> InvokeWriter<'a> {
+        out.extend_from_slice(header);
+
+        InvokeWriter { out, first: true }
+    }
+
+    /// Appends an argument to the parameter list.
+    pub fn arg<A: EncodeArg + ?Sized>(&mut self, arg: &A) -> &mut Self {
+        if !self.first {
+            self.out.push(b',');
+        }
+        self.first = false;
+
+        arg.encode_arg(self.out);
+        self
+    }
+
+    /// Appends every argument in a tuple to the parameter list.
+    pub fn args<A: EncodeArgs + ?Sized>(&mut self, args: &A) -> &mut Self {
+        args.encode_args(self);
+        self
+    }
+
+    /// Closes the parameter list and finishes the frame with the id of the device to call.
+    pub fn finish(self, device_id: &str) {
+        self.out.extend_from_slice(br#"],"deviceId":"#);
+        device_id.encode_arg(self.out);
+        self.out.extend_from_slice(b"}}");
+        self.out.push(FRAME_DELIMITER);
+    }
+
+    /// Finishes the frame with a footer rendered ahead of time; see [invoke_footer].
+    pub fn finish_with(self, footer: &[u8]) {
+        self.out.extend_from_slice(footer);
+    }
+}
+
+/// Renders the header [InvokeWriter::new] writes for a method, so it can be stored and reused with [InvokeWriter::with_header].
+pub fn invoke_header(method_name: &str) -> Vec<u8> {
+    let mut header = Vec::new();
+    InvokeWriter::new(&mut header, method_name);
+    header
+}
+
+/// Renders what [InvokeWriter::finish] writes for a device, so it can be stored and reused with [InvokeWriter::finish_with].
+pub fn invoke_footer(device_id: &str) -> Vec<u8> {
+    let mut footer = Vec::new();
+    InvokeWriter {
+        out: &mut footer,
+        first: true,
+    }
+    .finish(device_id);
+    footer
+}
+
+/// A list of method parameters, written one after the other. This is implemented for tuples of [EncodeArg]s, with `()` being no parameters at all.
+pub trait EncodeArgs {
+    fn encode_args(&self, call: &mut InvokeWriter<'_>);
+}
+
+macro_rules! encode_args_tuple {
+    ($(($($name:ident)*))*) => {$(
+        impl<$($name: EncodeArg),*> EncodeArgs for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn encode_args(&self, call: &mut InvokeWriter<'_>) {
+                let ($($name,)*) = self;
+                $(call.arg($name);)*
+            }
+        }
+    )*};
+}
+
+encode_args_tuple! {
+    ()
+    (A)
+    (A B)
+    (A B C)
+    (A B C D)
+    (A B C D E)
+    (A B C D E F)
+    (A B C D E F G)
+    (A B C D E F G H)
+}
+
+impl<T: EncodeArg + ?Sized> EncodeArg for &T {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        (**self).encode_arg(out)
+    }
+}
+
+impl EncodeArg for bool {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(if *self { b"true" } else { b"false" });
+    }
+}
+
+fn write_u64(mut n: u64, out: &mut Vec<u8>) {
+    let mut digits = [0u8; 20];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+
+    out.extend_from_slice(&digits[start..]);
+}
+
+macro_rules! encode_unsigned {
+    ($($t:ty)*) => {$(
+        impl EncodeArg for $t {
+            fn encode_arg(&self, out: &mut Vec<u8>) {
+                write_u64(*self as u64, out)
+            }
+        }
+    )*};
+}
+
+macro_rules! encode_signed {
+    ($($t:ty)*) => {$(
+        impl EncodeArg for $t {
+            fn encode_arg(&self, out: &mut Vec<u8>) {
+                if *self < 0 {
+                    out.push(b'-');
+                }
+                write_u64((*self as i64).unsigned_abs(), out)
+            }
+        }
+    )*};
+}
+
+encode_unsigned!(u16 u32 u64 usize);
+encode_signed!(i8 i16 i32 i64 isize);
+
+// the decimal digits of every byte value, along with how many of them there are.
+static BYTE_DIGITS: [(u8, [u8; 3]); 256] = byte_digits();
+
+const fn byte_digits() -> [(u8, [u8; 3]); 256] {
+    let mut table = [(0, [0; 3]); 256];
+    let mut i = 0;
+    while i < 256 {
+        let n = i as u8;
+        table[i] = if n >= 100 {
+            (3, [b'0' + n / 100, b'0' + n / 10 % 10, b'0' + n % 10])
+        } else if n >= 10 {
+            (2, [b'0' + n / 10, b'0' + n % 10, 0])
+        } else {
+            (1, [b'0' + n, 0, 0])
+        };
+        i += 1;
+    }
+
+    table
+}
+
+impl EncodeArg for u8 {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        let (len, digits) = &BYTE_DIGITS[*self as usize];
+        out.extend_from_slice(&digits[..*len as usize]);
+    }
+
+    /// Byte arrays are how files get moved around, so they skip the digit loop and reserve their whole length up front.
+    fn encode_slice(items: &[u8], out: &mut Vec<u8>) {
+        // "255," is the longest an element can get.
+        out.reserve(items.len() * 4 + 2);
+        out.push(b'[');
+        for (i, b) in items.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            let (len, digits) = &BYTE_DIGITS[*b as usize];
+            out.extend_from_slice(&digits[..*len as usize]);
+        }
+        out.push(b']');
+    }
+}
+struct VecWriter<'a>(&'a mut Vec<u8>);
+
+impl Write for VecWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+macro_rules! encode_float {
+    ($($t:ty)*) => {$(
+        impl EncodeArg for $t {
+            fn encode_arg(&self, out: &mut Vec<u8>) {
+                // json has no way to write nan or infinity, so they go out as null like serde_json does.
+                if self.is_finite() {
+                    let _ = write!(VecWriter(out), "{}", self);
+                } else {
+                    out.extend_from_slice(b"null");
+                }
+            }
+        }
+    )*};
+}
+
+encode_float!(f32 f64);
+
+impl EncodeArg for str {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        out.push(b'"');
+        let bytes = self.as_bytes();
+        let mut start = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            let escape: &[u8] = match b {
+                b'"' => b"\\\"",
+                b'\\' => b"\\\\",
+                b'\n' => b"\\n",
+                b'\r' => b"\\r",
+                b'\t' => b"\\t",
+                0..=0x1f => &[],
+                _ => continue,
+            };
+
+            out.extend_from_slice(&bytes[start..i]);
+            if escape.is_empty() {
+                out.extend_from_slice(b"\\u00");
+                out.push(HEX[(b >> 4) as usize]);
+                out.push(HEX[(b & 0xf) as usize]);
+            } else {
+                out.extend_from_slice(escape);
+            }
+            start = i + 1;
+        }
+        out.extend_from_slice(&bytes[start..]);
+        out.push(b'"');
+    }
+}
+
+impl EncodeArg for String {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_arg(out)
+    }
+}
+
+impl<T: EncodeArg> EncodeArg for [T] {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        T::encode_slice(self, out)
+    }
+}
+
+impl<T: EncodeArg> EncodeArg for Vec<T> {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_arg(out)
+    }
+}
+
+impl<T: EncodeArg, const N: usize> EncodeArg for [T; N] {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_arg(out)
+    }
+}
+
+impl<T: EncodeArg> EncodeArg for Option<T> {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(v) => v.encode_arg(out),
+            None => out.extend_from_slice(b"null"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_borrowed, RawValue};
+    use alloc::{format, vec};
+    use arrayvec::ArrayString;
+    use miniserde_miku::json;
+
+    #[test]
+    fn byte_slices_match_miniserde() {
+        let all: Vec<u8> = (0..=255).collect();
+        for bytes in [&all[..], &[], &[0], &[7, 42, 255]] {
+            let mut out = Vec::new();
+            bytes.encode_arg(&mut out);
+            let mut expected = ArrayString::<4096>::new();
+            json::to_string::<_, 4096, 1024>(&bytes.to_vec(), &mut expected);
+            assert_eq!(out, expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn byte_slices_round_trip() {
+        let all: Vec<u8> = (0..=255).rev().collect();
+        let mut out = Vec::new();
+        all[..].encode_arg(&mut out);
+
+        let frame = format!(
+            r#"{{"type":"result","data":{}}}"#,
+            core::str::from_utf8(&out).unwrap()
+        );
+        let data = decode_borrowed::<RawValue>(frame.as_bytes()).unwrap().data;
+        let mut bytes = vec![1, 2];
+        data.bytes_into(&mut bytes).unwrap();
+        assert_eq!(bytes[..2], [1, 2]);
+        assert_eq!(bytes[2..], all[..]);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{RPCResult, Response, ResultValue, WrappedRPCResult};
+use alloc::vec::Vec;
+use core::{fmt, str};
+use miniserde_miku::{json, Deserialize};
+
+/// The byte that opens and closes every HLApi frame.
+pub const FRAME_DELIMITER: u8 = 0;
+
+/// Reassembles a frame from the chunks read off the bus.
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    complete: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder::with_capacity(2048)
+    }
+
+    pub fn with_capacity(capacity: usize) -> FrameDecoder {
+        FrameDecoder {
+            buf: Vec::with_capacity(capacity),
+            complete: false,
+        }
+    }
+
+    /// Discards any partially or fully received frame, keeping the allocated buffer around.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.complete = false;
+    }
+
+    /// Feeds a chunk of bytes into the decoder; returns the payload of the frame once its closing delimiter has been seen.
+    ///
+    /// Delimiters before the start of a frame are skipped, and anything after its end is discarded until [FrameDecoder::clear] is called.
+    pub fn feed(&mut self, mut chunk: &[u8]) -> Option<&[u8]> {
+        if !self.complete {
+            if self.buf.is_empty() {
+                let start = chunk
+                    .iter()
+                    .position(|&b| b != FRAME_DELIMITER)
+                    .unwrap_or(chunk.len());
+                chunk = &chunk[start..];
+            }
+
+            match chunk.iter().position(|&b| b == FRAME_DELIMITER) {
+                Some(end) => {
+                    self.buf.extend_from_slice(&chunk[..end]);
+                    self.complete = true;
+                }
+                None => self.buf.extend_from_slice(chunk),
+            }
+        }
+
+        self.frame()
+    }
+
+    /// Returns the payload of the received frame, if it is complete.
+    pub fn frame(&self) -> Option<&[u8]> {
+        if self.complete {
+            Some(&self.buf)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> FrameDecoder {
+        FrameDecoder::new()
+    }
+}
+
+/// Decodes the payload of a response frame, as returned by [FrameDecoder::feed].
+///
+/// A missing or `null` `data` field is accepted if `R` accepts it as a struct field would, see [ResultValue::into_data]; use [decode_result] to tell the two apart.
+pub fn decode_response<R: Deserialize>(frame: &[u8]) -> Result<Response<R>, CodecError> {
+    let Response { msg_type, data } = decode_result(frame)?;
+
+    Ok(Response {
+        msg_type,
+        data: data.into_data().ok_or(CodecError::Malformed)?,
+    })
+}
+
+/// Decodes the payload of a response frame, keeping track of whether the method returned nothing, `null` or a value.
+pub fn decode_result<R: Deserialize>(frame: &[u8]) -> Result<Response<ResultValue<R>>, CodecError> {
+    let frame = str::from_utf8(frame).map_err(|_| CodecError::InvalidUtf8)?;
+    let res: RPCResult<ResultValue<R>> = json::from_str::<WrappedRPCResult<R>>(frame)
+        .map_err(|_| CodecError::Malformed)?
+        .into();
+
+    res.map_err(CodecError::Rpc)
+}
+
+/// An error produced while encoding a call or decoding a response.
+pub enum CodecError {
+    /// The call does not fit within [crate::MAX_MESSAGE_SIZE].
+    MessageTooLarge,
+    /// The frame is not valid UTF-8.
+    InvalidUtf8,
+    /// The frame is not a well-formed response of the expected type.
+    Malformed,
+    /// The HLApi answered with an error.
+    Rpc(crate::RPCError),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::MessageTooLarge => f.write_str("message too large"),
+            CodecError::InvalidUtf8 => f.write_str("frame is not valid utf-8"),
+            CodecError::Malformed => f.write_str("malformed response"),
+            CodecError::Rpc(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl fmt::Debug for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
+
+#[cfg(feature = "std")]
+impl From<CodecError> for std::io::Error {
+    fn from(e: CodecError) -> std::io::Error {
+        use std::io::{Error, ErrorKind};
+
+        match e {
+            CodecError::MessageTooLarge => Error::new(ErrorKind::InvalidInput, e),
+            CodecError::Rpc(e) => Error::new(ErrorKind::InvalidData, e),
+            e => Error::new(ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn frame_split_across_reads() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.feed(b"\0{\"type\":"), None);
+        assert_eq!(decoder.feed(b""), None);
+        assert_eq!(decoder.feed(b"\"result\""), None);
+        assert_eq!(
+            decoder.feed(b",\"data\":1}\0"),
+            Some(&br#"{"type":"result","data":1}"#[..])
+        );
+    }
+
+    #[test]
+    fn delimiter_in_its_own_read() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.feed(b"\0"), None);
+        assert_eq!(decoder.feed(b"\0\0{}"), None);
+        assert_eq!(decoder.feed(b"\0"), Some(&b"{}"[..]));
+    }
+
+    #[test]
+    fn rest_of_read_is_discarded_until_cleared() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.feed(b"\0{}\0\0[]"), Some(&b"{}"[..]));
+        assert_eq!(decoder.feed(b"\0"), Some(&b"{}"[..]));
+
+        decoder.clear();
+        assert_eq!(decoder.frame(), None);
+        assert_eq!(decoder.feed(b"[]\0"), Some(&b"[]"[..]));
+    }
+
+    #[test]
+    fn decodes_split_frame() {
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(b"\0{\"data\":4");
+        let frame = decoder.feed(b"2,\"type\":\"result\"}\0").unwrap();
+        assert_eq!(decode_response::<u32>(frame).unwrap().data, 42);
+    }
+
+    #[test]
+    fn data_before_type() {
+        let frame = br#"{"data":[[1,2],[],[3]],"type":"result"}"#;
+        assert_eq!(
+            decode_response::<Vec<Vec<u32>>>(frame).unwrap().data,
+            [vec![1, 2], vec![], vec![3]]
+        );
+
+        // early data that doesn't fit the type fails the same way it would have without being kept around.
+        let frame = br#"{"data":{"ignored":[-1,2.5,null,true]},"type":"result"}"#;
+        assert!(decode_response::<()>(frame).is_err());
+
+        let frame = br#"{"data":"unknown device","type":"error"}"#;
+        assert!(matches!(
+            decode_response::<u32>(frame),
+            Err(CodecError::Rpc(crate::RPCError::UnknownDevice))
+        ));
+        let frame = br#"{"data":"out of range","type":"error"}"#;
+        assert!(matches!(
+            decode_result::<u32>(frame),
+            Err(CodecError::Rpc(crate::RPCError::Other(e))) if e == "out of range"
+        ));
+    }
+
+    #[test]
+    fn result_values() {
+        for (frame, value) in [
+            (&br#"{"type":"result"}"#[..], ResultValue::Void),
+            (br#"{"type":"result","data":null}"#, ResultValue::Null),
+            (br#"{"data":null,"type":"result"}"#, ResultValue::Null),
+            (br#"{"type":"result","data":7}"#, ResultValue::Value(7)),
+            (br#"{"data":7,"type":"result"}"#, ResultValue::Value(7)),
+        ] {
+            assert_eq!(decode_result::<u32>(frame).unwrap().data, value);
+        }
+
+        // only types that take a missing or null value can be decoded from one.
+        assert_eq!(ResultValue::<Option<u32>>::Void.into_data(), Some(None));
+        assert_eq!(ResultValue::<Option<u32>>::Null.into_data(), Some(None));
+        assert_eq!(ResultValue::<()>::Null.into_data(), Some(()));
+        assert_eq!(ResultValue::<u32>::Null.into_data(), None);
+        assert!(decode_response::<u32>(br#"{"type":"result"}"#).is_err());
+        assert_eq!(ResultValue::Value(3u32).into_data(), Some(3));
+    }
+}
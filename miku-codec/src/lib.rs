@@ -0,0 +1,23 @@
+//! A `no_std` codec for the OpenComputers 2 HLApi message format.
+//!
+//! This crate only turns calls into frames and frames into responses; it knows nothing about the device those frames travel over.
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod borrowed;
+pub use borrowed::*;
+
+mod call;
+pub use call::*;
+
+mod encode;
+pub use encode::*;
+
+mod frame;
+pub use frame::*;
+
+mod response;
+pub use response::*;
@@ -0,0 +1,367 @@
+use miniserde_miku as miniserde;
+
+use miniserde::de::{Deserialize, Map, Visitor};
+use miniserde::json::{Number, Value};
+use miniserde::ser::{Fragment, Serialize};
+use miniserde::{make_place, Result as MiniserdeResult};
+
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::convert::Infallible;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::CodecError;
+
+make_place!(Place);
+
+pub type RPCResult<T> = core::result::Result<Response<T>, RPCError>;
+
+pub(crate) enum WrappedRPCResult<T: Deserialize> {
+    Ok(Response<ResultValue<T>>),
+    Err(RPCError),
+}
+
+impl<T: Deserialize> From<WrappedRPCResult<T>> for RPCResult<ResultValue<T>> {
+    fn from(res: WrappedRPCResult<T>) -> RPCResult<ResultValue<T>> {
+        match res {
+            WrappedRPCResult::Ok(v) => Result::Ok(v),
+            WrappedRPCResult::Err(e) => Result::Err(e),
+        }
+    }
+}
+
+/// The response to a HLApi call.
+pub struct Response<T> {
+    pub msg_type: MessageType,
+    pub data: T,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Response<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("type", &self.msg_type)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+/// What a method call produced, as told by the `data` field of its response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultValue<T> {
+    /// The method returns nothing; the response had no `data` field.
+    Void,
+    /// The method returned `null`.
+    Null,
+    /// The method returned a value.
+    Value(T),
+}
+
+impl<T: Deserialize> ResultValue<T> {
+    /// Collapses this into a plain `T`, the same way miniserde treats a missing or `null` struct field: `Option<T>` turns both into `None`, `()` accepts `null`, and most other types need a value.
+    pub fn into_data(self) -> Option<T> {
+        match self {
+            ResultValue::Void => T::default(),
+            ResultValue::Null => {
+                let mut out = None;
+                T::begin(&mut out).null().ok()?;
+                out
+            }
+            ResultValue::Value(v) => Some(v),
+        }
+    }
+}
+
+impl<T> ResultValue<T> {
+    /// Returns the value, if the method returned one.
+    pub fn value(self) -> Option<T> {
+        match self {
+            ResultValue::Value(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self, ResultValue::Void)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, ResultValue::Null)
+    }
+}
+
+/// An error for a string that doesn't name any value of an enum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseEnumError {
+    kind: &'static str,
+    value: String,
+}
+
+impl ParseEnumError {
+    /// Creates an error for `value`, which isn't a valid `kind`.
+    pub fn new(kind: &'static str, value: &str) -> ParseEnumError {
+        ParseEnumError {
+            kind,
+            value: value.to_owned(),
+        }
+    }
+
+    /// Returns the string that couldn't be parsed.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for ParseEnumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown {} {:?}", self.kind, self.value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseEnumError {}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MessageType {
+    List,
+    Methods,
+    Result,
+    Error,
+    Invoke, // never received, only sent
+}
+
+impl FromStr for MessageType {
+    type Err = ParseEnumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "list" => MessageType::List,
+            "methods" => MessageType::Methods,
+            "result" => MessageType::Result,
+            "error" => MessageType::Error,
+            "invoke" => MessageType::Invoke,
+            _ => return Err(ParseEnumError::new("message type", s)),
+        })
+    }
+}
+
+impl Visitor for Place<MessageType> {
+    fn string(&mut self, b: &str) -> MiniserdeResult<()> {
+        self.out = Some(b.parse().map_err(|_| miniserde::Error)?);
+        Ok(())
+    }
+}
+
+impl Deserialize for MessageType {
+    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl AsRef<str> for MessageType {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            MessageType::List => "list",
+            MessageType::Methods => "methods",
+            MessageType::Result => "result",
+            MessageType::Error => "error",
+            MessageType::Invoke => "invoke",
+        }
+    }
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl fmt::Debug for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_ref())
+    }
+}
+
+impl Serialize for MessageType {
+    fn begin(&self) -> Fragment {
+        Fragment::Str(Cow::Borrowed(self.as_ref()))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum RPCError {
+    MessageTooLarge,
+    UnknownMessageType,
+    UnknownDevice,
+    UnknownMethod,
+    InvalidParameterSignature,
+    /// Any other error message, kept as is.
+    Other(String),
+}
+
+/// Never fails: messages that aren't one of the known errors become [RPCError::Other].
+impl FromStr for RPCError {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "message too large" => RPCError::MessageTooLarge,
+            "unknown message type" => RPCError::UnknownMessageType,
+            "unknown device" => RPCError::UnknownDevice,
+            "unknown method" => RPCError::UnknownMethod,
+            "invalid parameter signature" => RPCError::InvalidParameterSignature,
+            _ => RPCError::Other(s.to_owned()),
+        })
+    }
+}
+
+impl Visitor for Place<RPCError> {
+    fn string(&mut self, b: &str) -> MiniserdeResult<()> {
+        let Ok(error) = b.parse();
+        self.out = Some(error);
+        Ok(())
+    }
+}
+
+impl Deserialize for RPCError {
+    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+impl AsRef<str> for RPCError {
+    fn as_ref(&self) -> &str {
+        match self {
+            RPCError::MessageTooLarge => "message too large",
+            RPCError::UnknownMessageType => "unknown message type",
+            RPCError::UnknownDevice => "unknown device",
+            RPCError::UnknownMethod => "unknown method",
+            RPCError::InvalidParameterSignature => "invalid parameter signature",
+            RPCError::Other(ref s) => s,
+        }
+    }
+}
+
+impl fmt::Display for RPCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl fmt::Debug for RPCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_ref())
+    }
+}
+
+impl Serialize for RPCError {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Str(Cow::Borrowed(self.as_ref()))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RPCError {}
+
+struct ResponseBuilder<'a, T: Deserialize> {
+    msg_type: Option<MessageType>,
+    error: Option<RPCError>,
+    // None while the data field hasn't been seen, Some(None) if it was null.
+    data: Option<Option<T>>,
+    // a data field that came before the type field, kept until we know whether it holds an error or a result.
+    early_data: Option<Value>,
+    out: &'a mut Option<WrappedRPCResult<T>>,
+}
+
+impl<'a, T: Deserialize> Map for ResponseBuilder<'a, T> {
+    fn key(&mut self, k: &str) -> MiniserdeResult<&mut dyn Visitor> {
+        match k {
+            "type" => Ok(Deserialize::begin(&mut self.msg_type)),
+            "data" => match self.msg_type {
+                Some(MessageType::Error) => Ok(Deserialize::begin(&mut self.error)),
+                Some(_) => Ok(Deserialize::begin(&mut self.data)),
+                None => Ok(Deserialize::begin(&mut self.early_data)),
+            },
+            _ => Ok(<dyn Visitor>::ignore()),
+        }
+    }
+
+    fn finish(&mut self) -> MiniserdeResult<()> {
+        let msg_type = self.msg_type.take().ok_or(miniserde::Error)?;
+
+        if let Some(ref data) = self.early_data.take() {
+            if let MessageType::Error = msg_type {
+                replay(data, Deserialize::begin(&mut self.error))?;
+            } else {
+                replay(data, Deserialize::begin(&mut self.data))?;
+            }
+        }
+
+        *self.out = Some(if let MessageType::Error = msg_type {
+            WrappedRPCResult::Err(self.error.take().ok_or(miniserde::Error)?)
+        } else {
+            WrappedRPCResult::Ok(Response {
+                msg_type,
+                data: match self.data.take() {
+                    None => ResultValue::Void,
+                    Some(None) => ResultValue::Null,
+                    Some(Some(v)) => ResultValue::Value(v),
+                },
+            })
+        });
+
+        Ok(())
+    }
+}
+
+/// Decodes an already parsed json value, the same way it would have been decoded straight out of a frame.
+pub fn from_value<T: Deserialize>(value: &Value) -> Result<T, CodecError> {
+    let mut out = None;
+    replay(value, T::begin(&mut out)).map_err(|_| CodecError::Malformed)?;
+    out.ok_or(CodecError::Malformed)
+}
+
+/// Feeds an already parsed json value into a visitor, as if it was being parsed for the first time.
+fn replay(value: &Value, visitor: &mut dyn Visitor) -> MiniserdeResult<()> {
+    match value {
+        Value::Null => visitor.null(),
+        Value::Bool(b) => visitor.boolean(*b),
+        Value::Number(Number::U64(n)) => visitor.nonnegative(*n),
+        Value::Number(Number::I64(n)) => visitor.negative(*n),
+        Value::Number(Number::F64(n)) => visitor.float(*n),
+        Value::String(s) => visitor.string(s),
+        Value::Array(values) => {
+            let mut seq = visitor.seq()?;
+            for v in values.iter() {
+                replay(v, seq.element()?)?;
+            }
+            seq.finish()
+        }
+        Value::Object(entries) => {
+            let mut map = visitor.map()?;
+            for (k, v) in entries.iter() {
+                replay(v, map.key(k)?)?;
+            }
+            map.finish()
+        }
+    }
+}
+
+impl<T: Deserialize> Visitor for Place<WrappedRPCResult<T>> {
+    fn map(&mut self) -> MiniserdeResult<Box<dyn Map + '_>> {
+        Ok(Box::new(ResponseBuilder {
+            msg_type: None,
+            error: None,
+            data: None,
+            early_data: None,
+            out: &mut self.out,
+        }))
+    }
+}
+
+impl<T: Deserialize> Deserialize for WrappedRPCResult<T> {
+    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
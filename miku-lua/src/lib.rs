@@ -49,8 +49,8 @@ impl BusHandle {
         serialized_call.append(&mut serialized);
         serialized_call.push(0);
 
-        bus.call_preserialized::<miniserde_miku::json::Value>(&serialized_call)
-            .map(|v| miniserde_types::WrappedJSONValue::from(v.data))
+        bus.call_preserialized_result::<miniserde_miku::json::Value>(&serialized_call)
+            .map(|v| miniserde_types::WrappedJSONValue::from(v.value().unwrap_or_default()))
             .map_err(LuaError::external)
     }
 }
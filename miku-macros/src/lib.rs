@@ -1,15 +1,21 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::{
     bracketed, parse_macro_input,
     token::{self, Comma},
-    Attribute, FnArg, Ident, LitStr, Pat, Path, Result, ReturnType, Signature, Token,
+    Attribute, Fields, FnArg, GenericArgument, Generics, Ident, ItemStruct, ItemTrait, LitStr, Pat,
+    PatType, Path, PathArguments, Result, ReturnType, Signature, Token, TraitItem, Type,
 };
 
 mod kw {
     syn::custom_keyword!(docs);
+    syn::custom_keyword!(async_name);
+    syn::custom_keyword!(identities);
+    syn::custom_keyword!(capabilities);
+    syn::custom_keyword!(requires);
 }
 
 const OC2_DOC_BASE: &str =
@@ -28,30 +34,69 @@ impl Parse for FnDef {
 struct OC2RpcDef {
     oc_method_name: LitStr,
     doc_path: Option<LitStr>,
+    async_name: Option<LitStr>,
 }
 
 impl Parse for OC2RpcDef {
     fn parse(input: ParseStream) -> Result<Self> {
-        Ok(OC2RpcDef {
-            oc_method_name: input.parse::<LitStr>()?,
-            doc_path: if input.peek(Token![,]) {
-                input.parse::<Token![,]>()?;
+        let oc_method_name = input.parse::<LitStr>()?;
+        let mut doc_path = None;
+        let mut async_name = None;
+
+        while input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::docs) {
                 input.parse::<kw::docs>()?;
                 input.parse::<Token![=]>()?;
-                Some(input.parse::<LitStr>()?)
+                doc_path = Some(input.parse::<LitStr>()?);
+            } else if lookahead.peek(kw::async_name) {
+                input.parse::<kw::async_name>()?;
+                input.parse::<Token![=]>()?;
+                async_name = Some(input.parse::<LitStr>()?);
             } else {
-                None
-            },
+                return Err(lookahead.error());
+            }
+        }
+
+        Ok(OC2RpcDef {
+            oc_method_name,
+            doc_path,
+            async_name,
         })
     }
 }
 
+/// Implements a HLApi method as a trait method that calls it over a `DeviceBus`, along with an `_async` twin for the `AsyncDeviceBus` when miku-rpc's `async` feature is on.
+///
+/// The twin's name can be set with `async_name = "..."`, for methods whose names already end in `_async`.
+///
+/// OC2 picks between methods with the same name by how many parameters they're called with. Overloads can be declared as separate functions with the same method name, and trailing `Option` parameters are left out of the call when they're `None`, so one function can cover several of them:
+///
+/// ```ignore
+/// #[rpc("playSound")]
+/// fn play_default_sound(name: &str);
+///
+/// #[rpc("playSound")]
+/// fn play_sound(name: &str, volume: f64, pitch: f64);
+///
+/// #[rpc("getItems")]
+/// fn get_items(slot: Option<i32>, count: Option<i32>) -> Vec<String>;
+/// ```
 #[proc_macro_attribute]
 pub fn rpc(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let def = parse_macro_input!(attr as OC2RpcDef);
+    let method = parse_macro_input!(input as FnDef);
+
+    TokenStream::from(expand_rpc(def, method).unwrap_or_else(|e| e.to_compile_error()))
+}
+
+fn expand_rpc(def: OC2RpcDef, method: FnDef) -> Result<TokenStream2> {
     let OC2RpcDef {
         oc_method_name,
         doc_path,
-    } = parse_macro_input!(attr as OC2RpcDef);
+        async_name,
+    } = def;
     let FnDef(
         Signature {
             ident,
@@ -61,13 +106,14 @@ pub fn rpc(attr: TokenStream, input: TokenStream) -> TokenStream {
             ..
         },
         attrs,
-    ) = parse_macro_input!(input as FnDef);
+    ) = method;
     let where_clause = &generics.where_clause;
 
     let ret_type = match output {
-        ReturnType::Default => quote! { Option<()> },
-        ReturnType::Type(_, t) => quote! { #t },
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ref t) => quote! { #t },
     };
+    let is_void = matches!(output, ReturnType::Default);
 
     let doc_path = doc_path.map(|path| {
         let doc_url = format!("[OC2 Docs]({}/{}):", OC2_DOC_BASE, path.value());
@@ -76,57 +122,581 @@ pub fn rpc(attr: TokenStream, input: TokenStream) -> TokenStream {
         }
     });
 
-    let arg_idents: Vec<Box<Pat>> = inputs
+    let async_ident = async_ident(&ident, async_name.as_ref());
+    let async_doc = format!(
+        "Same as [Self::{}], but over an [crate::AsyncDeviceBus].",
+        ident
+    );
+    let async_generics = async_generics(&generics);
+
+    let typed_args: Vec<&PatType> = inputs
         .iter()
         .filter_map(|v| {
             if let FnArg::Typed(t) = v {
-                Some(t.pat.clone())
+                Some(t)
             } else {
                 None
             }
         })
         .collect();
+    let arg_idents: Vec<&Pat> = typed_args.iter().map(|t| &*t.pat).collect();
+    let arg_defs: Vec<&FnArg> = inputs.iter().collect();
 
-    let tokens = if arg_idents.is_empty() {
-        let mut call_bytes = (String::from("\0")
-            + &format!(
-                r#"{{"type":"invoke","data":{{"name":"{}","parameters": [],"deviceId":""#,
-                oc_method_name.value()
-            ))
-            .into_bytes();
-        let call_start_len = call_bytes.len();
-        let call_id_end_len = call_start_len + 36;
-        call_bytes.extend(std::iter::repeat(0).take(36));
-        call_bytes.extend(b"\"}}\0");
-        let call_full_len = call_bytes.len();
-
-        let call_iter = call_bytes.into_iter();
+    // trailing Option parameters are left out of the call when they're None, which is how overloads with fewer parameters get called.
+    let required = typed_args
+        .iter()
+        .position(|t| is_option(&t.ty))
+        .unwrap_or(typed_args.len());
+    if let Some(t) = typed_args[required..].iter().find(|t| !is_option(&t.ty)) {
+        return Err(syn::Error::new_spanned(
+            t,
+            "parameters after an Option have to be Options too, since OC2 tells overloads apart by how many parameters they get",
+        ));
+    }
+    let (required_args, optional_args) = arg_idents.split_at(required);
 
+    let mut optional_calls = quote! {};
+    for (i, arg) in optional_args.iter().enumerate().rev() {
+        let value = format_ident!("__rpc_arg_{}", i);
+        optional_calls = quote! {
+            if let Some(#value) = &#arg {
+                call.arg(#value);
+                #optional_calls
+            }
+        };
+    }
+    let gap_checks = optional_args.windows(2).map(|pair| {
+        let (before, after) = (pair[0], pair[1]);
+        let msg = format!(
+            "{} can't be given without {}",
+            quote! { #after },
+            quote! { #before }
+        );
         quote! {
-            #doc_path
-            #(#attrs)*
-            fn #ident #generics (&self, bus: &mut crate::DeviceBus) -> std::io::Result<#ret_type> #where_clause {
-                let mut call_bytes: [u8; #call_full_len ] = [ #(#call_iter),* ];
-                call_bytes[ #call_start_len .. #call_id_end_len].copy_from_slice(self.id().as_bytes());
+            if #after.is_some() && #before.is_none() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, #msg));
+            }
+        }
+    });
+    let gap_checks = quote! { #(#gap_checks)* };
+
+    let header = invoke_header(&oc_method_name.value());
+    let body = |awaiting: bool| {
+        if arg_idents.is_empty() {
+            let receive_cached = receive_response(
+                is_void,
+                &ret_type,
+                awaiting,
+                quote! { call_preserialized },
+                quote! { call_preserialized_result },
+                quote! { &frame },
+            );
+            let receive = receive_response(
+                is_void,
+                &ret_type,
+                awaiting,
+                quote! { call_with },
+                quote! { call_with_result },
+                quote! { render },
+            );
+
+            quote! {
+                let id = self.id();
+                let render = |frame: &mut Vec<u8>| crate::InvokeWriter::with_header(frame, #header).finish(id);
 
-                let response: crate::Response<#ret_type> = bus.call_preserialized(&call_bytes)?;
-                Ok(response.data)
+                match self.frame_cache() {
+                    Some(cache) => {
+                        let frame = cache.frame(#oc_method_name, render);
+                        #receive_cached
+                    }
+                    None => { #receive }
+                }
+            }
+        } else {
+            let receive = receive_response(
+                is_void,
+                &ret_type,
+                awaiting,
+                quote! { call_with },
+                quote! { call_with_result },
+                quote! {
+                    |frame| {
+                        let mut call = crate::InvokeWriter::with_header(frame, #header);
+                        #(call.arg(&#required_args);)*
+                        #optional_calls
+                        call.finish(self.id());
+                    }
+                },
+            );
+
+            quote! {
+                #gap_checks
+                #receive
             }
         }
+    };
+    let sync_body = body(false);
+    let async_body = body(true);
+
+    let prepare_ident = format_ident!("prepare_{}", ident);
+    let prepare_doc = format!(
+        "Prepares a call to [Self::{}] that can be sent over and over, with arguments passed as a tuple.",
+        ident
+    );
+    let prepared_args = typed_args.iter().map(|t| prepared_type(&t.ty));
+    let prepare = if is_void {
+        quote! { crate::PreparedCall::void(#header, self.id()) }
     } else {
-        let arg_defs = inputs.into_iter();
+        quote! { crate::PreparedCall::new(#header, self.id()) }
+    };
 
-        quote! {
-            #doc_path
-            #(#attrs)*
-            fn #ident #generics (&self, bus: &mut crate::DeviceBus, #(#arg_defs),*) -> std::io::Result<#ret_type> #where_clause {
-                let response: crate::Response<#ret_type> = bus.call(&crate::Call::invoke(self.id(), #oc_method_name, &[#(&#arg_idents),*]))?;
-                Ok(response.data)
+    let tokens = quote! {
+        #doc_path
+        #(#attrs)*
+        fn #ident #generics (&self, bus: &mut crate::DeviceBus, #(#arg_defs),*) -> std::io::Result<#ret_type> #where_clause {
+            #sync_body
+        }
+
+        #[doc = #prepare_doc]
+        fn #prepare_ident #generics (&self) -> crate::PreparedCall<(#(#prepared_args,)*), #ret_type> #where_clause {
+            #prepare
+        }
+
+        #[cfg(feature = "async")]
+        #[doc = #async_doc]
+        fn #async_ident #async_generics (&self, bus: &mut crate::AsyncDeviceBus<S>, #(#arg_defs),*) -> impl std::future::Future<Output = std::io::Result<#ret_type>> #where_clause {
+            async move {
+                #async_body
             }
         }
     };
 
-    TokenStream::from(tokens)
+    Ok(tokens)
+}
+
+// the type a parameter gets in the signature of a prepared call. References are made 'static, which lets them take borrows of any lifetime, and `impl Trait` turns into `ImplArg<dyn Trait>`, since it can't be named there.
+fn prepared_type(ty: &Type) -> Type {
+    match ty {
+        Type::ImplTrait(t) => {
+            let bounds = &t.bounds;
+            syn::parse_quote! { crate::ImplArg<dyn #bounds> }
+        }
+        Type::Reference(r) => {
+            let mut r = r.clone();
+            r.lifetime = Some(syn::parse_quote! { 'static });
+            r.elem = Box::new(prepared_type(&r.elem));
+            Type::Reference(r)
+        }
+        Type::Path(p) => {
+            let mut p = p.clone();
+            for segment in &mut p.path.segments {
+                if let PathArguments::AngleBracketed(args) = &mut segment.arguments {
+                    for arg in &mut args.args {
+                        if let GenericArgument::Type(t) = arg {
+                            *t = prepared_type(t);
+                        }
+                    }
+                }
+            }
+            Type::Path(p)
+        }
+        Type::Slice(s) => {
+            let mut s = s.clone();
+            s.elem = Box::new(prepared_type(&s.elem));
+            Type::Slice(s)
+        }
+        Type::Paren(p) => prepared_type(&p.elem),
+        Type::Group(g) => prepared_type(&g.elem),
+        ty => ty.clone(),
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|s| s.ident == "Option")
+        }
+        _ => false,
+    }
+}
+
+fn async_ident(ident: &Ident, async_name: Option<&LitStr>) -> Ident {
+    match async_name {
+        Some(name) => Ident::new(&name.value(), name.span()),
+        None => format_ident!("{}_async", ident),
+    }
+}
+
+// the generics of an async twin, which are the method's own plus the stream type of the bus.
+fn async_generics(generics: &Generics) -> Generics {
+    let mut generics = generics.clone();
+    generics.params.push(syn::parse_quote! {
+        S: futures_lite::AsyncRead + futures_lite::AsyncWrite + Unpin
+    });
+    generics
+}
+
+// the start of an invoke frame, up to the parameter list, rendered by the codec itself so the two can't drift apart.
+fn invoke_header(method_name: &str) -> Literal {
+    Literal::byte_string(&miku_codec::invoke_header(method_name))
+}
+
+// methods without a return type get their result checked for being void or null, rather than decoded into a value.
+fn receive_response(
+    is_void: bool,
+    ret_type: &TokenStream2,
+    awaiting: bool,
+    call_fn: TokenStream2,
+    result_fn: TokenStream2,
+    msg: TokenStream2,
+) -> TokenStream2 {
+    let await_call = if awaiting {
+        quote! { .await }
+    } else {
+        quote! {}
+    };
+
+    if is_void {
+        quote! {
+            let _: crate::ResultValue<()> = bus.#result_fn(#msg)#await_call?;
+            Ok(())
+        }
+    } else {
+        quote! {
+            let response: crate::Response<#ret_type> = bus.#call_fn(#msg)#await_call?;
+            Ok(response.data)
+        }
+    }
+}
+
+/// Goes on a trait made of `#[rpc]` methods, and generates a `Mock<Trait>` type for it when miku-rpc's `mock` feature is on, and a `Bound<Trait>` trait with the same methods for `Bound` handles, minus the bus argument.
+///
+/// The mock implements the trait by handing each `#[rpc]` method call to a closure set with `expect_<method>`, instead of going over the bus, so code that's generic over the trait can be tested without a running HLApi. Generic methods get closures that return a [json value](miniserde_miku::json::Value), decoded into whatever type the caller asked for.
+#[proc_macro_attribute]
+pub fn rpc_interface(attr: TokenStream, input: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return TokenStream::from(
+            syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "rpc_interface doesn't take any arguments",
+            )
+            .to_compile_error(),
+        );
+    }
+
+    let item = parse_macro_input!(input as ItemTrait);
+    let mock = expand_mock(&item).unwrap_or_else(|e| e.to_compile_error());
+    let bound = expand_bound(&item).unwrap_or_else(|e| e.to_compile_error());
+
+    TokenStream::from(quote! {
+        #item
+        #mock
+        #bound
+    })
+}
+
+// an #[rpc] method, as written in its trait.
+struct RpcMethod {
+    ident: Ident,
+    async_ident: Ident,
+    generics: Generics,
+    arg_pats: Vec<Pat>,
+    arg_types: Vec<Type>,
+    ret_type: TokenStream2,
+}
+
+fn rpc_methods(item: &ItemTrait) -> Result<Vec<RpcMethod>> {
+    let mut methods = Vec::new();
+    for trait_item in &item.items {
+        let method = match trait_item {
+            TraitItem::Method(m) => m,
+            _ => continue,
+        };
+        let attr = match method.attrs.iter().find(|a| a.path.is_ident("rpc")) {
+            Some(attr) => attr,
+            None => continue,
+        };
+        let OC2RpcDef { async_name, .. } = attr.parse_args::<OC2RpcDef>()?;
+
+        let sig = &method.sig;
+        let mut arg_pats = Vec::new();
+        let mut arg_types = Vec::new();
+        for arg in &sig.inputs {
+            match arg {
+                FnArg::Typed(t) => {
+                    arg_pats.push((*t.pat).clone());
+                    arg_types.push((*t.ty).clone());
+                }
+                FnArg::Receiver(r) => {
+                    return Err(syn::Error::new_spanned(
+                        r,
+                        "#[rpc] methods add their own receiver and bus arguments",
+                    ))
+                }
+            }
+        }
+
+        methods.push(RpcMethod {
+            ident: sig.ident.clone(),
+            async_ident: async_ident(&sig.ident, async_name.as_ref()),
+            generics: sig.generics.clone(),
+            arg_pats,
+            arg_types,
+            ret_type: match sig.output {
+                ReturnType::Default => quote! { () },
+                ReturnType::Type(_, ref t) => quote! { #t },
+            },
+        });
+    }
+
+    Ok(methods)
+}
+
+fn expand_mock(item: &ItemTrait) -> Result<TokenStream2> {
+    let trait_ident = &item.ident;
+    let vis = &item.vis;
+    let mock_ident = format_ident!("Mock{}", trait_ident);
+    let mock_doc = format!(
+        "A mock [{}], with each method answered by a closure instead of the bus. See [crate::mock].",
+        trait_ident
+    );
+    let methods = rpc_methods(item)?;
+
+    let mut fields = Vec::new();
+    let mut field_inits = Vec::new();
+    let mut setters = Vec::new();
+    let mut impls = Vec::new();
+    for method in &methods {
+        let RpcMethod {
+            ident,
+            async_ident,
+            generics,
+            arg_pats,
+            arg_types,
+            ret_type,
+        } = method;
+        let where_clause = &generics.where_clause;
+        let async_generics = async_generics(generics);
+
+        // generic methods can return anything, so their closures return json and leave decoding to the call.
+        let is_generic = !generics.params.is_empty();
+        let handler_ret = if is_generic {
+            quote! { miniserde_miku::json::Value }
+        } else {
+            quote! { #ret_type }
+        };
+        // impl Trait arguments can't be named in the closure's type, so they're handed to it as trait objects.
+        let handler_args: Vec<TokenStream2> = arg_types
+            .iter()
+            .map(|ty| match ty {
+                Type::ImplTrait(t) => {
+                    let bounds = &t.bounds;
+                    quote! { &dyn #bounds }
+                }
+                ty => quote! { #ty },
+            })
+            .collect();
+        let call_args = arg_pats.iter().zip(arg_types).map(|(pat, ty)| match ty {
+            Type::ImplTrait(_) => quote! { &#pat },
+            _ => quote! { #pat },
+        });
+        let call_args = quote! { #(#call_args),* };
+
+        let handler = quote! { dyn FnMut(#(#handler_args),*) -> std::io::Result<#handler_ret> };
+        let call = if is_generic {
+            quote! {
+                self.#ident
+                    .call(|handler| handler(#call_args))
+                    .and_then(|value| Ok(miku_codec::from_value(&value)?))
+            }
+        } else {
+            quote! { self.#ident.call(|handler| handler(#call_args)) }
+        };
+
+        let method_name = format!("{}::{}", mock_ident, ident);
+        let expect_ident = format_ident!("expect_{}", ident);
+        let expect_doc = format!(
+            "Sets the closure that answers calls to [{}::{}], replacing any that was set before.",
+            trait_ident, ident
+        );
+
+        fields.push(quote! { #ident: crate::mock::Expectation<#handler> });
+        field_inits.push(quote! { #ident: crate::mock::Expectation::new(#method_name) });
+        setters.push(quote! {
+            #[doc = #expect_doc]
+            pub fn #expect_ident(
+                &mut self,
+                handler: impl FnMut(#(#handler_args),*) -> std::io::Result<#handler_ret> + 'static,
+            ) -> &mut crate::mock::Expectation<#handler> {
+                self.#ident.set(Box::new(handler))
+            }
+        });
+        impls.push(quote! {
+            fn #ident #generics (&self, _bus: &mut crate::DeviceBus, #(#arg_pats: #arg_types),*) -> std::io::Result<#ret_type> #where_clause {
+                #call
+            }
+
+            #[cfg(feature = "async")]
+            fn #async_ident #async_generics (&self, _bus: &mut crate::AsyncDeviceBus<S>, #(#arg_pats: #arg_types),*) -> impl std::future::Future<Output = std::io::Result<#ret_type>> #where_clause {
+                let result = #call;
+                async move { result }
+            }
+        });
+    }
+    let idents = methods.iter().map(|m| &m.ident);
+
+    Ok(quote! {
+        #[cfg(feature = "mock")]
+        #[doc = #mock_doc]
+        #vis struct #mock_ident {
+            id: String,
+            #(#fields,)*
+        }
+
+        #[cfg(feature = "mock")]
+        impl #mock_ident {
+            pub fn new(id: impl Into<String>) -> Self {
+                #mock_ident {
+                    id: id.into(),
+                    #(#field_inits,)*
+                }
+            }
+
+            #(#setters)*
+        }
+
+        #[cfg(feature = "mock")]
+        impl RPCDevice for #mock_ident {
+            fn id(&self) -> &str {
+                &self.id
+            }
+
+            fn from_id(id: String) -> Self {
+                #mock_ident::new(id)
+            }
+        }
+
+        #[cfg(feature = "mock")]
+        impl #trait_ident for #mock_ident {
+            #(#impls)*
+        }
+
+        #[cfg(feature = "mock")]
+        impl Drop for #mock_ident {
+            fn drop(&mut self) {
+                if !std::thread::panicking() {
+                    #(self.#idents.verify();)*
+                }
+            }
+        }
+    })
+}
+
+fn expand_bound(item: &ItemTrait) -> Result<TokenStream2> {
+    let trait_ident = &item.ident;
+    let vis = &item.vis;
+    let bound_ident = format_ident!("Bound{}", trait_ident);
+    let bound_doc = format!(
+        "The methods of [{}], for [crate::Bound] handles, which bring their own bus.",
+        trait_ident
+    );
+
+    let mut decls = Vec::new();
+    let mut impls = Vec::new();
+    for trait_item in &item.items {
+        let method = match trait_item {
+            TraitItem::Method(m) => m,
+            _ => continue,
+        };
+        let is_rpc = method.attrs.iter().any(|a| a.path.is_ident("rpc"));
+        let docs = method.attrs.iter().filter(|a| a.path.is_ident("doc"));
+        let sig = &method.sig;
+
+        // #[rpc] methods are written without their receiver and bus, and provided methods that take a bus get it after their receiver.
+        let mut inputs = sig.inputs.iter().peekable();
+        if !is_rpc {
+            let takes_bus = matches!(inputs.next(), Some(FnArg::Receiver(r)) if r.reference.is_some() && r.mutability.is_none())
+                && matches!(inputs.peek(), Some(FnArg::Typed(t)) if is_bus_type(&t.ty));
+            if !takes_bus {
+                continue;
+            }
+            inputs.next();
+        }
+
+        let mut args = Vec::new();
+        let mut arg_pats = Vec::new();
+        for arg in inputs {
+            match arg {
+                FnArg::Typed(t) => {
+                    args.push(arg);
+                    arg_pats.push(&t.pat);
+                }
+                FnArg::Receiver(r) => {
+                    return Err(syn::Error::new_spanned(
+                        r,
+                        "#[rpc] methods add their own receiver and bus arguments",
+                    ))
+                }
+            }
+        }
+
+        let ident = &sig.ident;
+        let generics = &sig.generics;
+        let where_clause = &generics.where_clause;
+        let turbofish = generics.split_for_impl().1;
+        let turbofish = turbofish.as_turbofish();
+        let ret_type = match (&sig.output, is_rpc) {
+            (ReturnType::Type(_, t), true) => quote! { std::io::Result<#t> },
+            (ReturnType::Default, true) => quote! { std::io::Result<()> },
+            (ReturnType::Type(_, t), false) => quote! { #t },
+            (ReturnType::Default, false) => {
+                return Err(syn::Error::new_spanned(
+                    sig,
+                    "methods that take a bus have to return an io::Result",
+                ))
+            }
+        };
+
+        decls.push(quote! {
+            #(#docs)*
+            fn #ident #generics (&self, #(#args),*) -> #ret_type #where_clause;
+        });
+        impls.push(quote! {
+            fn #ident #generics (&self, #(#args),*) -> #ret_type #where_clause {
+                self.with_bus(move |device, bus| device.#ident #turbofish (bus, #(#arg_pats),*))
+            }
+        });
+    }
+
+    Ok(quote! {
+        #[doc = #bound_doc]
+        #vis trait #bound_ident {
+            #(#decls)*
+        }
+
+        impl<'__bus, D: #trait_ident> #bound_ident for crate::Bound<'__bus, D> {
+            #(#impls)*
+        }
+    })
+}
+
+fn is_bus_type(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(r) => match &*r.elem {
+            Type::Path(p) => p
+                .path
+                .segments
+                .last()
+                .is_some_and(|s| s.ident == "DeviceBus"),
+            _ => false,
+        },
+        _ => false,
+    }
 }
 
 struct DeviceData {
@@ -167,28 +737,199 @@ pub fn define_device(tokens: TokenStream) -> TokenStream {
         ..
     } = parse_macro_input!(tokens as DeviceData);
 
-    let capabilities = capabilities.into_iter();
+    let item: ItemStruct = syn::parse_quote! {
+        #[doc = #docs]
+        #[derive(Debug)]
+        pub struct #rust_name;
+    };
+    let args = DeviceArgs {
+        identities: vec![oc2_identity],
+        requires: Vec::new(),
+        capabilities: capabilities.into_iter().collect(),
+    };
 
-    let tokens = quote! {
-        #[doc = #docs ]
-        pub struct #rust_name(pub String);
+    TokenStream::from(expand_device(args, item).unwrap_or_else(|e| e.to_compile_error()))
+}
+
+struct DeviceArgs {
+    identities: Vec<LitStr>,
+    requires: Vec<LitStr>,
+    capabilities: Vec<Path>,
+}
+
+impl Parse for DeviceArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut identities = Vec::new();
+        let mut requires = Vec::new();
+        let mut capabilities = Vec::new();
+
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            let content;
+            if lookahead.peek(kw::identities) {
+                input.parse::<kw::identities>()?;
+                input.parse::<Token![=]>()?;
+                bracketed!(content in input);
+                identities.extend(content.parse_terminated::<LitStr, Comma>(|c| c.parse())?);
+            } else if lookahead.peek(kw::requires) {
+                input.parse::<kw::requires>()?;
+                input.parse::<Token![=]>()?;
+                bracketed!(content in input);
+                requires.extend(content.parse_terminated::<LitStr, Comma>(|c| c.parse())?);
+            } else if lookahead.peek(kw::capabilities) {
+                input.parse::<kw::capabilities>()?;
+                input.parse::<Token![=]>()?;
+                bracketed!(content in input);
+                capabilities.extend(content.parse_terminated::<Path, Comma>(Path::parse)?);
+            } else {
+                return Err(lookahead.error());
+            }
+
+            if !input.is_empty() {
+                input.parse::<Comma>()?;
+            }
+        }
+
+        Ok(DeviceArgs {
+            identities,
+            requires,
+            capabilities,
+        })
+    }
+}
+
+/// Turns a struct into a device wrapper, like `define_device!` does, but with any number of identities and room for extra fields.
+///
+/// `requires` lists type names a device must have all of to match, on top of one of its identities, for wrappers over blocks that expose several capabilities at once.
+///
+/// A unit struct becomes `Name(pub String, FrameCache)`, holding its id and the frames of its zero-argument methods, and is made with `RPCDevice::from_id`. A struct with named fields gets `pub id: String` and a private frame cache added in front of them; the rest of the fields start out as their [Default] value.
+///
+/// ```ignore
+/// /// A device capable of interacting with redstone
+/// #[device(identities = ["redstone"], capabilities = [RedstoneInterface])]
+/// pub struct RedstoneDevice;
+/// ```
+#[proc_macro_attribute]
+pub fn device(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as DeviceArgs);
+    let item = parse_macro_input!(input as ItemStruct);
+
+    TokenStream::from(expand_device(args, item).unwrap_or_else(|e| e.to_compile_error()))
+}
 
-        impl RPCDevice for #rust_name {
+fn expand_device(args: DeviceArgs, item: ItemStruct) -> Result<TokenStream2> {
+    let ItemStruct {
+        attrs,
+        vis,
+        ident,
+        generics,
+        fields,
+        ..
+    } = item;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let DeviceArgs {
+        identities,
+        requires,
+        capabilities,
+    } = args;
+    let identity = identities
+        .first()
+        .ok_or_else(|| syn::Error::new(ident.span(), "a device needs at least one identity"))?;
+
+    let (definition, id, cache, constructor) = match fields {
+        Fields::Unit => (
+            quote! { #vis struct #ident #generics (pub String, crate::wrappers::FrameCache) #where_clause; },
+            quote! { self.0 },
+            quote! { self.1 },
+            quote! { #ident(id, crate::wrappers::FrameCache::new()) },
+        ),
+        Fields::Named(named) => {
+            let fields = named.named.iter();
+            let field_names = named.named.iter().map(|f| &f.ident);
+            (
+                quote! {
+                    #vis struct #ident #generics #where_clause {
+                        pub id: String,
+                        frame_cache: crate::wrappers::FrameCache,
+                        #(#fields),*
+                    }
+                },
+                quote! { self.id },
+                quote! { self.frame_cache },
+                quote! {
+                    #ident {
+                        id,
+                        frame_cache: crate::wrappers::FrameCache::new(),
+                        #(#field_names: Default::default()),*
+                    }
+                },
+            )
+        }
+        Fields::Unnamed(unnamed) => {
+            return Err(syn::Error::new_spanned(
+                unnamed,
+                "device fields need names, since the id is added in front of them",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #definition
+
+        impl #impl_generics RPCDevice for #ident #ty_generics #where_clause {
             fn id(&self) -> &str {
-                &self.0
+                &#id
             }
 
             fn from_id(id: String) -> Self {
-                #rust_name(id)
+                #constructor
+            }
+
+            fn frame_cache(&self) -> Option<&crate::wrappers::FrameCache> {
+                Some(&#cache)
             }
         }
 
-        impl IdentifiedDevice for #rust_name {
-            const IDENTITY: &'static str = #oc2_identity;
+        impl #impl_generics IdentifiedDevice for #ident #ty_generics #where_clause {
+            const IDENTITY: &'static str = #identity;
+            const IDENTITIES: &'static [&'static str] = &[#(#identities),*];
+            const REQUIRES: &'static [&'static str] = &[#(#requires),*];
         }
 
-        #(impl #capabilities for #rust_name {})*
-    };
+        #(impl #impl_generics #capabilities for #ident #ty_generics #where_clause {})*
+    })
+}
 
-    TokenStream::from(tokens)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invoke_header_matches_codec() {
+        for name in ["getRedstoneInput", "say \"hi\"", "back\\slash"] {
+            let header: syn::LitByteStr = syn::parse_str(&invoke_header(name).to_string()).unwrap();
+            assert_eq!(header.value(), miku_codec::invoke_header(name));
+            assert_eq!(header.value()[0], b'\0');
+        }
+    }
+
+    fn expand(attr: &str, method: &str) -> Result<TokenStream2> {
+        let def = syn::parse_str::<OC2RpcDef>(attr)?;
+        let method = syn::parse_str::<FnDef>(method)?;
+        expand_rpc(def, method)
+    }
+
+    // what the generated code sends is tested against a bus in miku-rpc's wrappers; only errors are checked here.
+    #[test]
+    fn options_have_to_be_trailing() {
+        let err = expand(
+            r#""take""#,
+            "fn take(side: Option<RobotSide>, count: i32) -> i32;",
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().starts_with("parameters after an Option"));
+    }
 }
@@ -0,0 +1,75 @@
+//! Compares time per file transfer chunk between the miniserde path and the byte array path, in both directions.
+//!
+//! Run with `cargo bench --bench bytes`.
+
+use arrayvec::ArrayString;
+use miku_codec::{decode_borrowed, decode_response, encode_call, RawValue, MAX_MESSAGE_SIZE};
+use miku_rpc::{Call, InvokeWriter};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 20_000;
+const CHUNK_SIZE: usize = 900;
+const DEVICE_ID: &str = "5c3c8d36-2b0e-4d1e-8e5b-6f8f3b7a9c21";
+
+fn measure(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<24} {:>12?}/chunk {:>8.1} MB/s",
+        name,
+        elapsed / ITERATIONS,
+        (CHUNK_SIZE as u64 * ITERATIONS as u64) as f64 / elapsed.as_secs_f64() / 1_000_000.0
+    );
+}
+
+fn main() {
+    // a spread of one, two and three digit bytes, like a binary file would have.
+    let chunk: Vec<u8> = (0..CHUNK_SIZE).map(|i| (i * 97 % 256) as u8).collect();
+
+    let mut read = String::from(r#"{"type":"result","data":["#);
+    for (i, b) in chunk.iter().enumerate() {
+        if i > 0 {
+            read.push(',');
+        }
+        read.push_str(&b.to_string());
+    }
+    read.push_str("]}");
+    let read = read.as_bytes();
+
+    let mut message = ArrayString::<MAX_MESSAGE_SIZE>::new();
+    measure("write/miniserde", || {
+        let data = &chunk[..];
+        encode_call(
+            &Call::invoke(DEVICE_ID, "writeExportFile", &[&data]),
+            &mut message,
+        )
+        .unwrap();
+        black_box(message.len());
+    });
+
+    let mut frame = Vec::with_capacity(MAX_MESSAGE_SIZE);
+    measure("write/bytes", || {
+        frame.clear();
+        let mut call = InvokeWriter::new(&mut frame, "writeExportFile");
+        call.arg(&chunk[..]);
+        call.finish(DEVICE_ID);
+        black_box(frame.len());
+    });
+
+    measure("read/miniserde", || {
+        black_box(decode_response::<Option<Vec<u8>>>(read).unwrap().data);
+    });
+
+    let mut buf = Vec::new();
+    measure("read/bytes", || {
+        buf.clear();
+        let data = decode_borrowed::<Option<RawValue>>(read).unwrap().data;
+        data.unwrap().bytes_into(&mut buf).unwrap();
+        black_box(buf.len());
+    });
+}
@@ -0,0 +1,101 @@
+//! Compares allocations and time per decoded response between the miniserde path and the borrowed path.
+//!
+//! Run with `cargo bench --bench decode`.
+
+use miku_codec::{decode_borrowed, decode_response, LazySeq};
+use miku_rpc::types::{DeviceData, DeviceDataRef};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 100_000;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn measure(name: &str, mut f: impl FnMut()) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{:<24} {:>8.1} allocs/call {:>12?}/call",
+        name,
+        allocations as f64 / ITERATIONS as f64,
+        elapsed / ITERATIONS
+    );
+}
+
+fn main() {
+    let mut list = String::from(r#"{"type":"list","data":["#);
+    for i in 0..24 {
+        if i > 0 {
+            list.push(',');
+        }
+        list.push_str(&format!(
+            r#"{{"deviceId":"5c3c8d36-2b0e-4d1e-8e5b-6f8f3b7a{:04}","typeNames":["item_handler","energy_storage","fluid_handler"]}}"#,
+            i
+        ));
+    }
+    list.push_str(
+        r#",{"deviceId":"0f6a1b44-98d7-4c52-a1e3-2d4b5c6e7f80","typeNames":["redstone"]}]}"#,
+    );
+    let list = list.as_bytes();
+    let int = br#"{"type":"result","data":15}"#;
+    let action = br#"{"type":"result","data":"SUCCESS"}"#;
+
+    measure("list/miniserde", || {
+        let devices = decode_response::<Vec<DeviceData>>(list).unwrap().data;
+        black_box(
+            devices
+                .iter()
+                .find(|d| d.type_names.iter().any(|t| t == "redstone")),
+        );
+    });
+    measure("list/borrowed", || {
+        let devices = decode_borrowed::<LazySeq<DeviceDataRef>>(list)
+            .unwrap()
+            .data;
+        black_box(
+            devices
+                .iter()
+                .map(Result::unwrap)
+                .find(|d| d.type_names.iter().any(|t| t.unwrap() == "redstone")),
+        );
+    });
+
+    measure("int/miniserde", || {
+        black_box(decode_response::<i32>(int).unwrap().data);
+    });
+    measure("int/borrowed", || {
+        black_box(decode_borrowed::<i32>(int).unwrap().data);
+    });
+
+    measure("string/miniserde", || {
+        black_box(decode_response::<String>(action).unwrap().data);
+    });
+    measure("string/borrowed", || {
+        black_box(decode_borrowed::<Cow<str>>(action).unwrap().data);
+    });
+}
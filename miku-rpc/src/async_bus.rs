@@ -0,0 +1,331 @@
+#[cfg(feature = "wrappers")]
+use crate::wrappers::IdentifiedDevice;
+use crate::{types::DeviceDataRef, Call, Response, ResultValue};
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use miku_codec::{
+    decode_borrowed, decode_response, decode_result, encode_call, CodecError, DecodeBorrowed,
+    FrameDecoder, LazySeq, MAX_MESSAGE_SIZE,
+};
+use miniserde_miku::{Deserialize, Serialize};
+
+use std::io;
+
+use arrayvec::ArrayString;
+
+/// An async bus interface to the HLApi, over any stream that can be read from and written to asynchronously.
+///
+/// This doesn't open the device itself, since how that's done depends on the runtime: open it with whatever your runtime uses for async files (like `async_io::Async<File>`), and put it in raw mode with [crate::set_raw_mode] first.
+///
+/// If a call's future is dropped after its message was sent, the next call skips over the response it left behind.
+pub struct AsyncDeviceBus<S> {
+    stream: S,
+    buffer: [u8; 4096],
+    write_buffer: ArrayString<MAX_MESSAGE_SIZE>,
+    template_buffer: Vec<u8>,
+    frame: FrameDecoder,
+    awaiting_response: bool,
+}
+
+// where the message for a call is, so it can be written out by [AsyncDeviceBus::exchange].
+enum Outgoing<'a> {
+    Message,
+    Template,
+    Bytes(&'a [u8]),
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDeviceBus<S> {
+    pub fn new(stream: S) -> AsyncDeviceBus<S> {
+        AsyncDeviceBus {
+            stream,
+            buffer: [0; 4096],
+            write_buffer: ArrayString::new(),
+            template_buffer: Vec::with_capacity(MAX_MESSAGE_SIZE),
+            frame: FrameDecoder::new(),
+            awaiting_response: false,
+        }
+    }
+
+    /// Returns the stream this bus talks over.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Calls a HLApi method and gets its response.
+    pub async fn call<T: Serialize, R: Deserialize>(
+        &mut self,
+        msg: &Call<T>,
+    ) -> io::Result<Response<R>> {
+        encode_call(msg, &mut self.write_buffer)?;
+        Ok(decode_response(self.exchange(Outgoing::Message).await?)?)
+    }
+
+    /// Calls a HLApi method and gets its response, from a pre-serialized message.
+    pub async fn call_preserialized<R: Deserialize>(
+        &mut self,
+        msg: &[u8],
+    ) -> io::Result<Response<R>> {
+        Ok(decode_response(self.exchange(Outgoing::Bytes(msg)).await?)?)
+    }
+
+    /// Calls a HLApi method with a frame rendered by `write`; see [crate::DeviceBus::call_with].
+    pub async fn call_with<R: Deserialize>(
+        &mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<Response<R>> {
+        self.render(write)?;
+        Ok(decode_response(self.exchange(Outgoing::Template).await?)?)
+    }
+
+    /// Calls a HLApi method and gets its result, telling apart methods that return nothing from ones that returned null.
+    pub async fn call_result<T: Serialize, R: Deserialize>(
+        &mut self,
+        msg: &Call<T>,
+    ) -> io::Result<ResultValue<R>> {
+        encode_call(msg, &mut self.write_buffer)?;
+        Ok(decode_result(self.exchange(Outgoing::Message).await?)?.data)
+    }
+
+    /// Same as [AsyncDeviceBus::call_result], but with a pre-serialized message like [AsyncDeviceBus::call_preserialized].
+    pub async fn call_preserialized_result<R: Deserialize>(
+        &mut self,
+        msg: &[u8],
+    ) -> io::Result<ResultValue<R>> {
+        Ok(decode_result(self.exchange(Outgoing::Bytes(msg)).await?)?.data)
+    }
+
+    /// Same as [AsyncDeviceBus::call_result], but with a frame rendered like [AsyncDeviceBus::call_with].
+    pub async fn call_with_result<R: Deserialize>(
+        &mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<ResultValue<R>> {
+        self.render(write)?;
+        Ok(decode_result(self.exchange(Outgoing::Template).await?)?.data)
+    }
+
+    /// Calls a HLApi method and decodes its response straight out of the bus' read buffer; see [crate::DeviceBus::call_borrowed].
+    pub async fn call_borrowed<'a, T: Serialize, R: DecodeBorrowed<'a>>(
+        &'a mut self,
+        msg: &Call<T>,
+    ) -> io::Result<Response<R>> {
+        encode_call(msg, &mut self.write_buffer)?;
+        Ok(decode_borrowed(self.exchange(Outgoing::Message).await?)?)
+    }
+
+    /// Same as [AsyncDeviceBus::call_borrowed], but with a frame rendered like [AsyncDeviceBus::call_with].
+    pub async fn call_with_borrowed<'a, R: DecodeBorrowed<'a>>(
+        &'a mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<Response<R>> {
+        self.render(write)?;
+        Ok(decode_borrowed(self.exchange(Outgoing::Template).await?)?)
+    }
+
+    /// Utility method to create a wrapper for a device of a certain type.
+    #[cfg(feature = "wrappers")]
+    pub async fn wrap<T: IdentifiedDevice>(&mut self) -> io::Result<Option<T>> {
+        let devices: crate::types::DeviceList = self.call(&Call::list()).await?;
+        Ok(devices
+            .data
+            .into_iter()
+            .find(T::matches)
+            .map(|device| T::from_id(device.device_id)))
+    }
+
+    /// Utility method to create wrappers for every device of a certain type.
+    #[cfg(feature = "wrappers")]
+    pub async fn wrap_all<T: IdentifiedDevice>(&mut self) -> io::Result<Vec<T>> {
+        let devices: crate::types::DeviceList = self.call(&Call::list()).await?;
+        Ok(devices
+            .data
+            .into_iter()
+            .filter(T::matches)
+            .map(|device| T::from_id(device.device_id))
+            .collect())
+    }
+
+    /// Lists every device on the bus, each wrapped as the wrapper type that fits it best.
+    #[cfg(feature = "wrappers")]
+    pub async fn devices(&mut self) -> io::Result<Vec<crate::wrappers::KnownDevice>> {
+        let devices: crate::types::DeviceList = self.call(&Call::list()).await?;
+        Ok(devices.data.into_iter().map(Into::into).collect())
+    }
+
+    /// Utility method to find a device id for a certain device type.
+    pub async fn find(&mut self, kind: &str) -> io::Result<Option<String>> {
+        let devices: Response<LazySeq<DeviceDataRef>> = self.call_borrowed(&Call::list()).await?;
+        for device in devices.data.iter() {
+            let device = device?;
+            for type_name in device.type_names.iter() {
+                if type_name? == kind {
+                    return Ok(Some(device.device_id.into_owned()));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn render(&mut self, write: impl FnOnce(&mut Vec<u8>)) -> io::Result<()> {
+        self.template_buffer.clear();
+        write(&mut self.template_buffer);
+        if self.template_buffer.len() > MAX_MESSAGE_SIZE {
+            return Err(CodecError::MessageTooLarge.into());
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message and waits for the frame that answers it.
+    async fn exchange(&mut self, outgoing: Outgoing<'_>) -> io::Result<&[u8]> {
+        if self.awaiting_response {
+            self.read_frame().await?;
+        }
+        self.frame.clear();
+
+        let msg = match outgoing {
+            Outgoing::Message => self.write_buffer.as_bytes(),
+            Outgoing::Template => &self.template_buffer,
+            Outgoing::Bytes(msg) => msg,
+        };
+        self.stream.write_all(msg).await?;
+        self.stream.flush().await?;
+        self.awaiting_response = true;
+
+        self.read_frame().await?;
+        Ok(self.frame.frame().unwrap_or_default())
+    }
+
+    // reads until the frame decoder has a whole frame. it isn't cleared first, so a frame that was partially read by a dropped call is picked back up where it was left.
+    async fn read_frame(&mut self) -> io::Result<()> {
+        while self.frame.frame().is_none() {
+            let bytes_read = self.stream.read(&mut self.buffer).await?;
+            if bytes_read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.frame.feed(&self.buffer[..bytes_read]);
+        }
+
+        self.awaiting_response = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future::block_on;
+    use futures_lite::io::AssertAsync;
+    use std::os::unix::net::UnixStream;
+
+    fn serve(
+        respond: impl FnMut(&str) -> String + Send + 'static,
+    ) -> AsyncDeviceBus<AssertAsync<UnixStream>> {
+        AsyncDeviceBus::new(AssertAsync::new(crate::bus::serve_stream(respond).unwrap()))
+    }
+
+    const LIST: &str = r#"{"type":"list","data":[{"deviceId":"a","typeNames":["energy_storage"]},{"deviceId":"b","typeNames":["item_handler","redstone"]}]}"#;
+
+    #[test]
+    fn calls_and_finds() {
+        let mut bus = serve(|request| {
+            if request.contains(r#""type":"list""#) {
+                LIST.to_owned()
+            } else {
+                r#"{"type":"result","data":[1,2]}"#.to_owned()
+            }
+        });
+
+        block_on(async {
+            let devices: crate::types::DeviceList = bus.call(&Call::list()).await.unwrap();
+            assert_eq!(devices.data.len(), 2);
+            assert_eq!(devices.data[1].type_names, ["item_handler", "redstone"]);
+
+            assert_eq!(bus.find("redstone").await.unwrap().as_deref(), Some("b"));
+            assert_eq!(bus.find("sound").await.unwrap(), None);
+
+            let response: Response<Vec<u8>> = bus.call(&Call::methods("a")).await.unwrap();
+            assert_eq!(response.data, [1, 2]);
+        });
+    }
+
+    #[test]
+    fn errors_come_back_as_errors() {
+        let mut bus = serve(|_| r#"{"type":"error","data":"unknown device"}"#.to_owned());
+
+        let err = block_on(bus.call::<_, i32>(&Call::methods("a"))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unknown device");
+    }
+
+    #[cfg(feature = "wrappers")]
+    #[test]
+    fn async_twins_send_the_same_calls() {
+        use crate::types::Side;
+        use crate::wrappers::{RPCDevice, RedstoneDevice, RedstoneInterface};
+        use std::sync::{Arc, Mutex};
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        let mut bus = serve(move |request| {
+            log.lock().unwrap().push(request.to_owned());
+            if request.contains("getRedstoneInput") {
+                r#"{"type":"result","data":7}"#.to_owned()
+            } else {
+                r#"{"type":"result"}"#.to_owned()
+            }
+        });
+        let redstone = RedstoneDevice::from_id("r".to_owned());
+
+        block_on(async {
+            assert_eq!(
+                redstone
+                    .get_redstone_input_async(&mut bus, Side::Up)
+                    .await
+                    .unwrap(),
+                7
+            );
+            redstone
+                .set_redstone_output_async(&mut bus, "back", 15)
+                .await
+                .unwrap();
+        });
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"getRedstoneInput","parameters":["up"],"deviceId":"r"}}"#,
+                r#"{"type":"invoke","data":{"name":"setRedstoneOutput","parameters":["back",15],"deviceId":"r"}}"#,
+            ]
+        );
+    }
+
+    #[cfg(feature = "wrappers")]
+    #[test]
+    fn wraps_devices() {
+        use crate::wrappers::{
+            EnergyStorageDevice, ItemHandlerDevice, RPCDevice, RedstoneDevice, SoundCard,
+        };
+
+        let mut bus = serve(|_| LIST.to_owned());
+        block_on(async {
+            let redstone: RedstoneDevice = bus.wrap().await.unwrap().unwrap();
+            assert_eq!(redstone.id(), "b");
+            assert!(bus.wrap::<SoundCard>().await.unwrap().is_none());
+
+            let storage: Vec<EnergyStorageDevice> = bus.wrap_all().await.unwrap();
+            assert_eq!(storage.len(), 1);
+            let kinds: Vec<_> = bus
+                .devices()
+                .await
+                .unwrap()
+                .iter()
+                .map(|d| d.kind())
+                .collect();
+            assert_eq!(kinds, ["EnergyStorageDevice", "RedstoneDevice"]);
+            assert_eq!(
+                bus.wrap_all::<ItemHandlerDevice>().await.unwrap()[0].id(),
+                "b"
+            );
+        });
+    }
+}
@@ -0,0 +1,175 @@
+use crate::wrappers::{IdentifiedDevice, RPCDevice};
+use crate::DeviceBus;
+use std::cell::{RefCell, RefMut};
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+
+/// A device together with the bus it's called through, so its methods don't need the bus passed in.
+///
+/// The methods come from a `Bound<Trait>` trait generated next to each wrapper trait, like [crate::wrappers::BoundRobotInterface] for [crate::wrappers::RobotInterface]. Handles share the bus through a [RefCell], so any number of them can be handed around at once, as long as they're only used one call at a time.
+///
+/// ```no_run
+/// # use miku_rpc::wrappers::{BoundRedstoneInterface, RedstoneDevice};
+/// # use miku_rpc::{Bound, DeviceBus};
+/// # use std::cell::RefCell;
+/// # fn main() -> std::io::Result<()> {
+/// let bus = RefCell::new(DeviceBus::new("/dev/hvc0")?);
+/// let redstone: Bound<RedstoneDevice> = Bound::wrap(&bus)?.expect("no redstone device!");
+///
+/// redstone.set_redstone_output("front", 15)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Bound<'a, D> {
+    device: D,
+    bus: &'a RefCell<DeviceBus>,
+}
+
+impl<'a, D: RPCDevice> Bound<'a, D> {
+    pub fn new(device: D, bus: &'a RefCell<DeviceBus>) -> Bound<'a, D> {
+        Bound { device, bus }
+    }
+
+    /// Wraps the first device of this type on the bus, like [DeviceBus::wrap].
+    pub fn wrap(bus: &'a RefCell<DeviceBus>) -> io::Result<Option<Bound<'a, D>>>
+    where
+        D: IdentifiedDevice,
+    {
+        let device = borrow_bus(bus)?.wrap()?;
+        Ok(device.map(|device| Bound::new(device, bus)))
+    }
+
+    /// Wraps every device of this type on the bus, like [DeviceBus::wrap_all].
+    pub fn wrap_all(bus: &'a RefCell<DeviceBus>) -> io::Result<Vec<Bound<'a, D>>>
+    where
+        D: IdentifiedDevice,
+    {
+        let devices = borrow_bus(bus)?.wrap_all()?;
+        Ok(devices
+            .into_iter()
+            .map(|device| Bound::new(device, bus))
+            .collect())
+    }
+
+    pub fn bus(&self) -> &'a RefCell<DeviceBus> {
+        self.bus
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Calls `f` with the device and the bus, for calls the generated traits don't cover.
+    ///
+    /// Fails instead of panicking if the bus is already in use, like from inside another call to this.
+    pub fn with_bus<R>(
+        &self,
+        f: impl FnOnce(&D, &mut DeviceBus) -> io::Result<R>,
+    ) -> io::Result<R> {
+        f(&self.device, &mut *borrow_bus(self.bus)?)
+    }
+}
+
+fn borrow_bus(bus: &RefCell<DeviceBus>) -> io::Result<RefMut<'_, DeviceBus>> {
+    bus.try_borrow_mut()
+        .map_err(|_| io::Error::other("the device bus is already in use"))
+}
+
+impl<D> Deref for Bound<'_, D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        &self.device
+    }
+}
+
+impl<D: fmt::Debug> fmt::Debug for Bound<'_, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Bound").field(&self.device).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::{
+        BoundEnergyStorage, BoundRedstoneInterface, EnergyStorage, EnergyStorageDevice,
+        RedstoneDevice,
+    };
+    use std::sync::{Arc, Mutex};
+
+    // a bus with a redstone interface "r" and two energy cells, "a" and "b", that logs the calls it gets.
+    fn bus() -> (RefCell<DeviceBus>, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        let bus = DeviceBus::serve(move |request| {
+            log.lock().unwrap().push(request.to_owned());
+            if request.contains(r#""type":"list""#) {
+                r#"{"type":"list","data":[{"deviceId":"r","typeNames":["redstone"]},{"deviceId":"a","typeNames":["energy_storage"]},{"deviceId":"b","typeNames":["energy_storage"]}]}"#
+            } else if request.contains("setRedstoneOutput") {
+                r#"{"type":"result"}"#
+            } else {
+                r#"{"type":"result","data":3}"#
+            }
+            .to_owned()
+        })
+        .unwrap();
+
+        (RefCell::new(bus), requests)
+    }
+
+    #[test]
+    fn handles_call_through_their_bus() {
+        let (bus, requests) = bus();
+        let redstone: Bound<RedstoneDevice> = Bound::wrap(&bus).unwrap().unwrap();
+        redstone.set_redstone_output("front", 15).unwrap();
+        assert_eq!(redstone.get_redstone_input("back").unwrap(), 3);
+        // the device is still there to read through the handle.
+        assert_eq!(redstone.id(), "r");
+
+        assert_eq!(
+            requests.lock().unwrap()[1..],
+            [
+                r#"{"type":"invoke","data":{"name":"setRedstoneOutput","parameters":["front",15],"deviceId":"r"}}"#,
+                r#"{"type":"invoke","data":{"name":"getRedstoneInput","parameters":["back"],"deviceId":"r"}}"#,
+            ]
+        );
+        assert_eq!(redstone.into_inner().id(), "r");
+    }
+
+    #[test]
+    fn handles_share_a_bus() {
+        let (bus, requests) = bus();
+        let cells: Vec<Bound<EnergyStorageDevice>> = Bound::wrap_all(&bus).unwrap();
+        assert_eq!(cells.len(), 2);
+
+        let total: i32 = cells
+            .iter()
+            .map(|cell| cell.get_energy_stored().unwrap())
+            .sum();
+        assert_eq!(total, 6);
+        assert!(requests.lock().unwrap()[1].contains(r#""deviceId":"a""#));
+        assert!(requests.lock().unwrap()[2].contains(r#""deviceId":"b""#));
+        assert!(std::ptr::eq(cells[0].bus(), cells[1].bus()));
+    }
+
+    #[test]
+    fn a_busy_bus_is_an_error() {
+        let (bus, _) = bus();
+        let cell = Bound::new(EnergyStorageDevice::from_id("a".to_owned()), &bus);
+        let redstone = Bound::new(RedstoneDevice::from_id("r".to_owned()), &bus);
+
+        let err = cell
+            .with_bus(|_, _| redstone.get_redstone_input("up"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "the device bus is already in use");
+
+        // the bus is free again once the outer call is done.
+        let stored = cell
+            .with_bus(|cell, bus| cell.get_energy_stored(bus))
+            .unwrap();
+        assert_eq!(stored, 3);
+        assert!(Bound::<RedstoneDevice>::wrap(&bus).unwrap().is_some());
+    }
+}
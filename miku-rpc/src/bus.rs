@@ -1,46 +1,78 @@
 #[cfg(feature = "wrappers")]
 use crate::wrappers::IdentifiedDevice;
-use crate::{types::DeviceList, Call, RPCResult, Response, WrappedRPCResult};
+use crate::{types::DeviceDataRef, Call, Response, ResultValue};
 use epoll_rs::{Epoll, Opts as PollOpts};
-use miniserde_miku::{json, Deserialize, Serialize};
+use miku_codec::{
+    decode_borrowed, decode_response, decode_result, encode_call, CodecError, DecodeBorrowed,
+    FrameDecoder, LazySeq, MAX_MESSAGE_SIZE,
+};
+use miniserde_miku::{Deserialize, Serialize};
 
 use std::fs::File;
 use std::io::{self, Read, Write};
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
 use std::path::Path;
 
-use std::str;
 use std::time::Duration;
 use termios::*;
 
 use arrayvec::ArrayString;
 
+/// Puts the terminal the HLApi is exposed through into raw mode, with echo off, so frames go through untouched.
+///
+/// [DeviceBus::new] does this on its own; it's only needed when opening the device some other way, like for an [crate::AsyncDeviceBus].
+pub fn set_raw_mode(fd: &impl AsRawFd) -> io::Result<()> {
+    let mut termios = Termios::from_fd(fd.as_raw_fd())?;
+    cfmakeraw(&mut termios);
+    termios.c_lflag &= !ECHO;
+    tcsetattr(fd.as_raw_fd(), TCSANOW, &termios)
+}
+
 /// A bus interface to the HLApi
 pub struct DeviceBus {
     file: File,
     buffer: [u8; 4096],
-    write_buffer: ArrayString<4096>,
-    string_buf: String,
+    write_buffer: ArrayString<MAX_MESSAGE_SIZE>,
+    template_buffer: Vec<u8>,
+    frame: FrameDecoder,
     poller: Epoll,
 }
 
 impl DeviceBus {
     pub fn new(path: impl AsRef<Path>) -> io::Result<DeviceBus> {
         let inner_f = File::options().read(true).write(true).open(path)?;
+        set_raw_mode(&inner_f)?;
+
+        DeviceBus::from_file(inner_f)
+    }
+
+    /// Creates a bus that isn't connected to anything, for code that takes a bus but won't really call through it, like code running against mock devices. Every call on it fails.
+    pub fn detached() -> io::Result<DeviceBus> {
+        // the other end is dropped right away, so reads see end of file and writes fail.
+        let (ours, _) = UnixStream::pair()?;
 
+        DeviceBus::from_file(File::from(OwnedFd::from(ours)))
+    }
+
+    /// Creates a bus whose other end answers every frame written to it with `respond`, from a thread of its own, to stand in for the HLApi in tests.
+    #[cfg(test)]
+    pub(crate) fn serve(
+        respond: impl FnMut(&str) -> String + Send + 'static,
+    ) -> io::Result<DeviceBus> {
+        DeviceBus::from_file(File::from(OwnedFd::from(serve_stream(respond)?)))
+    }
+
+    fn from_file(inner_f: File) -> io::Result<DeviceBus> {
         let poller = Epoll::new()?;
         let inner_f = poller.add(inner_f, PollOpts::IN)?.into_file();
 
-        let mut termios = Termios::from_fd(inner_f.as_raw_fd())?;
-        cfmakeraw(&mut termios);
-        termios.c_lflag &= !ECHO;
-        tcsetattr(inner_f.as_raw_fd(), TCSANOW, &termios)?;
-
         Ok(DeviceBus {
             file: inner_f,
             buffer: [0; 4096],
-            string_buf: String::with_capacity(2048),
-            write_buffer: ArrayString::<4096>::new(),
+            frame: FrameDecoder::new(),
+            write_buffer: ArrayString::new(),
+            template_buffer: Vec::with_capacity(MAX_MESSAGE_SIZE),
             poller,
         })
     }
@@ -49,84 +81,173 @@ impl DeviceBus {
     pub fn call<T: Serialize, R: Deserialize>(&mut self, msg: &Call<T>) -> io::Result<Response<R>> {
         self.flush()?;
         self.write_message(msg)?;
-        self.read_message()
+        Ok(decode_response(self.read_frame()?)?)
     }
 
     /// Calls a HLApi method and gets its response. Uses a pre-serialized string to help with optimizations for zero-argument functions.
     pub fn call_preserialized<R: Deserialize>(&mut self, msg: &[u8]) -> io::Result<Response<R>> {
         self.flush()?;
         self.file.write_all(msg)?;
-        self.read_message()
+        Ok(decode_response(self.read_frame()?)?)
+    }
+
+    /// Calls a HLApi method with a frame rendered by `write`, skipping miniserde entirely. This is what the `#[rpc]` macro uses for methods with arguments, together with [crate::InvokeWriter].
+    pub fn call_with<R: Deserialize>(
+        &mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<Response<R>> {
+        self.flush()?;
+        self.write_with(write)?;
+        Ok(decode_response(self.read_frame()?)?)
+    }
+
+    /// Same as [DeviceBus::call_result], but with a frame rendered like [DeviceBus::call_with].
+    pub fn call_with_result<R: Deserialize>(
+        &mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<ResultValue<R>> {
+        self.flush()?;
+        self.write_with(write)?;
+        Ok(decode_result(self.read_frame()?)?.data)
+    }
+
+    /// Calls a HLApi method and gets its result, telling apart methods that return nothing from ones that returned null.
+    pub fn call_result<T: Serialize, R: Deserialize>(
+        &mut self,
+        msg: &Call<T>,
+    ) -> io::Result<ResultValue<R>> {
+        self.flush()?;
+        self.write_message(msg)?;
+        Ok(decode_result(self.read_frame()?)?.data)
+    }
+
+    /// Same as [DeviceBus::call_result], but with a pre-serialized message like [DeviceBus::call_preserialized].
+    pub fn call_preserialized_result<R: Deserialize>(
+        &mut self,
+        msg: &[u8],
+    ) -> io::Result<ResultValue<R>> {
+        self.flush()?;
+        self.file.write_all(msg)?;
+        Ok(decode_result(self.read_frame()?)?.data)
+    }
+
+    /// Calls a HLApi method and decodes its response straight out of the bus' read buffer, borrowing strings and arrays from it instead of allocating them.
+    pub fn call_borrowed<'a, T: Serialize, R: DecodeBorrowed<'a>>(
+        &'a mut self,
+        msg: &Call<T>,
+    ) -> io::Result<Response<R>> {
+        self.flush()?;
+        self.write_message(msg)?;
+        Ok(decode_borrowed(self.read_frame()?)?)
+    }
+
+    /// Same as [DeviceBus::call_borrowed], but with a pre-serialized message like [DeviceBus::call_preserialized].
+    pub fn call_preserialized_borrowed<'a, R: DecodeBorrowed<'a>>(
+        &'a mut self,
+        msg: &[u8],
+    ) -> io::Result<Response<R>> {
+        self.flush()?;
+        self.file.write_all(msg)?;
+        Ok(decode_borrowed(self.read_frame()?)?)
+    }
+
+    /// Same as [DeviceBus::call_borrowed], but with a frame rendered like [DeviceBus::call_with].
+    pub fn call_with_borrowed<'a, R: DecodeBorrowed<'a>>(
+        &'a mut self,
+        write: impl FnOnce(&mut Vec<u8>),
+    ) -> io::Result<Response<R>> {
+        self.flush()?;
+        self.write_with(write)?;
+        Ok(decode_borrowed(self.read_frame()?)?)
     }
 
     /// Utility method to create a wrapper for a device of a certain type.
     #[cfg(feature = "wrappers")]
     pub fn wrap<T: IdentifiedDevice>(&mut self) -> io::Result<Option<T>> {
-        Ok(self.find(T::IDENTITY)?.map(T::from_id))
+        let devices: crate::types::DeviceList = self.call(&Call::list())?;
+        Ok(devices
+            .data
+            .into_iter()
+            .find(T::matches)
+            .map(|device| T::from_id(device.device_id)))
     }
 
-    /// Utility method to find a device id for a certain device type.
-    pub fn find(&mut self, kind: &str) -> io::Result<Option<String>> {
-        let device_list: DeviceList = self.call(&Call::list())?;
-        Ok(device_list
+    /// Utility method to create wrappers for every device of a certain type.
+    #[cfg(feature = "wrappers")]
+    pub fn wrap_all<T: IdentifiedDevice>(&mut self) -> io::Result<Vec<T>> {
+        let devices: crate::types::DeviceList = self.call(&Call::list())?;
+        Ok(devices
             .data
             .into_iter()
-            .find(|v| v.type_names.iter().any(|s| s == kind))
-            .map(|v| v.device_id))
+            .filter(T::matches)
+            .map(|device| T::from_id(device.device_id))
+            .collect())
     }
 
-    fn write_message<T: Serialize>(&mut self, msg: &Call<T>) -> io::Result<()> {
-        self.write_buffer.clear();
-        self.write_buffer.push('\0');
-        json::to_string::<_, 4096, 16384>(msg, &mut self.write_buffer);
-        self.write_buffer.push('\0');
+    /// Lists every device on the bus, each wrapped as the wrapper type that fits it best.
+    #[cfg(feature = "wrappers")]
+    pub fn devices(&mut self) -> io::Result<Vec<crate::wrappers::KnownDevice>> {
+        let devices: crate::types::DeviceList = self.call(&Call::list())?;
+        Ok(devices.data.into_iter().map(Into::into).collect())
+    }
 
-        self.file.write_all(self.write_buffer.as_bytes())?;
-        Ok(())
+    /// Utility method to find a device id for a certain device type.
+    pub fn find(&mut self, kind: &str) -> io::Result<Option<String>> {
+        let devices: Response<LazySeq<DeviceDataRef>> = self.call_borrowed(&Call::list())?;
+        for device in devices.data.iter() {
+            let device = device?;
+            for type_name in device.type_names.iter() {
+                if type_name? == kind {
+                    return Ok(Some(device.device_id.into_owned()));
+                }
+            }
+        }
+
+        Ok(None)
     }
 
-    fn read_message<R: Deserialize>(&mut self) -> io::Result<Response<R>> {
-        let mut bytes_read = self.read()?;
+    fn write_message<T: Serialize>(&mut self, msg: &Call<T>) -> io::Result<()> {
+        encode_call(msg, &mut self.write_buffer)?;
+        self.file.write_all(self.write_buffer.as_bytes())
+    }
 
-        if unsafe { *self.buffer.get_unchecked(bytes_read - 1) } != 0u8 {
-            self.string_buf
-                .push_str(unsafe { str::from_utf8_unchecked(&self.buffer[1..bytes_read]) });
+    fn write_with(&mut self, write: impl FnOnce(&mut Vec<u8>)) -> io::Result<()> {
+        self.template_buffer.clear();
+        write(&mut self.template_buffer);
+        if self.template_buffer.len() > MAX_MESSAGE_SIZE {
+            return Err(CodecError::MessageTooLarge.into());
+        }
 
-            loop {
-                bytes_read = self.read()?;
+        self.file.write_all(&self.template_buffer)
+    }
 
-                if unsafe { *self.buffer.get_unchecked(bytes_read - 1) } == 0u8 {
-                    self.string_buf.push_str(unsafe {
-                        str::from_utf8_unchecked(&self.buffer[..bytes_read - 1])
-                    });
-                    break;
-                } else {
-                    self.string_buf
-                        .push_str(unsafe { str::from_utf8_unchecked(&self.buffer[..bytes_read]) });
-                }
+    fn read_frame(&mut self) -> io::Result<&[u8]> {
+        loop {
+            let bytes_read = self.read()?;
+            if bytes_read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
             }
-        } else {
-            self.string_buf
-                .push_str(unsafe { str::from_utf8_unchecked(&self.buffer[1..bytes_read - 1]) });
-        }
 
-        let res: RPCResult<R> = json::from_str::<WrappedRPCResult<R>>(&self.string_buf)
-            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?
-            .into();
+            if self.frame.feed(&self.buffer[..bytes_read]).is_some() {
+                break;
+            }
+        }
 
-        res.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        Ok(self.frame.frame().unwrap_or_default())
     }
 
-    #[allow(clippy::unused_io_amount)]
     fn flush(&mut self) -> io::Result<()> {
-        self.string_buf.clear();
+        self.frame.clear();
 
         while self
             .poller
             .wait_one_timeout(Duration::from_secs(0))?
             .is_some()
         {
-            self.file.read(&mut self.buffer)?;
+            // a closed file stays readable forever, so stop once there's nothing left in it.
+            if self.file.read(&mut self.buffer)? == 0 {
+                break;
+            }
         }
 
         Ok(())
@@ -138,3 +259,93 @@ impl DeviceBus {
         self.file.read(&mut self.buffer)
     }
 }
+
+/// The stream behind [DeviceBus::serve], for buses that don't wrap a file, like an [crate::AsyncDeviceBus].
+#[cfg(test)]
+pub(crate) fn serve_stream(
+    mut respond: impl FnMut(&str) -> String + Send + 'static,
+) -> io::Result<UnixStream> {
+    let (ours, theirs) = UnixStream::pair()?;
+    let mut reader = io::BufReader::new(theirs.try_clone()?);
+    let mut writer = theirs;
+
+    std::thread::spawn(move || {
+        let mut frame = Vec::new();
+        // ends once the bus is dropped, and reads see end of file.
+        while matches!(io::BufRead::read_until(&mut reader, 0, &mut frame), Ok(1..)) {
+            if let Some(request) = frame.strip_suffix(b"\0").filter(|f| !f.is_empty()) {
+                let reply = respond(std::str::from_utf8(request).unwrap());
+                if writer
+                    .write_all(format!("\0{}\0", reply).as_bytes())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            frame.clear();
+        }
+    });
+
+    Ok(ours)
+}
+
+#[cfg(all(test, feature = "wrappers"))]
+mod tests {
+    use super::*;
+    use crate::wrappers::{EnergyStorageDevice, FluidHandlerDevice, Machine, RPCDevice, Robot};
+
+    // a chest, a cell, a machine, and a tank that has power but no inventory.
+    const LIST: &str = r#"{"type":"list","data":[
+        {"deviceId":"chest","typeNames":["item_handler"]},
+        {"deviceId":"cell","typeNames":["energy_storage"]},
+        {"deviceId":"furnace","typeNames":["item_handler","fluid_handler","energy_storage"]},
+        {"deviceId":"tank","typeNames":["fluid_handler","energy_storage"]}
+    ]}"#;
+
+    fn ids<D: RPCDevice>(devices: &[D]) -> Vec<&str> {
+        devices.iter().map(RPCDevice::id).collect()
+    }
+
+    #[test]
+    fn wraps_every_matching_device() {
+        let mut bus = DeviceBus::serve(|_| LIST.to_owned()).unwrap();
+
+        let storage: Vec<EnergyStorageDevice> = bus.wrap_all().unwrap();
+        assert_eq!(ids(&storage), ["cell", "furnace", "tank"]);
+        let tanks: Vec<FluidHandlerDevice> = bus.wrap_all().unwrap();
+        assert_eq!(ids(&tanks), ["furnace", "tank"]);
+        assert!(bus.wrap_all::<Robot>().unwrap().is_empty());
+        assert!(bus.wrap::<Robot>().unwrap().is_none());
+    }
+
+    #[test]
+    fn machines_need_every_capability() {
+        let mut bus = DeviceBus::serve(|_| LIST.to_owned()).unwrap();
+
+        // the chest and the tank each have some of a machine's type names, but only the furnace has all of them.
+        let machines: Vec<Machine> = bus.wrap_all().unwrap();
+        assert_eq!(ids(&machines), ["furnace"]);
+        assert_eq!(bus.wrap::<Machine>().unwrap().unwrap().id(), "furnace");
+    }
+
+    #[test]
+    fn lists_devices_as_their_best_wrapper() {
+        let mut bus = DeviceBus::serve(|_| LIST.to_owned()).unwrap();
+
+        let devices = bus.devices().unwrap();
+        let kinds: Vec<_> = devices.iter().map(|d| (d.id(), d.kind())).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("chest", "ItemHandlerDevice"),
+                ("cell", "EnergyStorageDevice"),
+                ("furnace", "Machine"),
+                ("tank", "EnergyStorageDevice"),
+            ]
+        );
+        assert_eq!(
+            bus.find("fluid_handler").unwrap().as_deref(),
+            Some("furnace")
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use crate::nbt::{ParseError, Parser, Tag, TagPath};
+use crate::types::ItemStack;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// A filter matching item stacks by their id and tag, written like `minecraft:diamond_pickaxe with Damage < 100`.
+///
+/// Filters are built out of:
+/// - item ids, which can use `*` as a wildcard and default to the `minecraft` namespace: `*_pickaxe`, `create:*`.
+/// - comparisons between a path into the item's tag and a SNBT value: `Damage < 100`, `display.Name ~ "Sword"`, `Unbreakable == 1b`.
+///   `~` checks for a substring, a list element, or a subset of a compound's entries, so `Enchantments ~ {id:"minecraft:mending"}` finds mending.
+///   Comparing a path the tag doesn't have is always false.
+/// - `has <path>`, for checking that a path exists at all.
+/// - `and`, `or`, `not` and parentheses. `with` is the same as `and`.
+///
+/// ```
+/// use miku_rpc::filter::ItemFilter;
+/// use miku_rpc::types::ItemStack;
+///
+/// let filter: ItemFilter = "*_pickaxe with Damage < 100 and not has Unbreakable".parse().unwrap();
+/// let stack: ItemStack =
+///     miniserde_miku::json::from_str(r#"{"id":"minecraft:iron_pickaxe","Count":1,"tag":{"Damage":42}}"#).unwrap();
+///
+/// assert!(filter.matches(&stack));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemFilter(Expr);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Id(String),
+    Has(TagPath),
+    Compare(TagPath, Op, Tag),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+// longer operators go first, so `<=` isn't read as `<`.
+const OPS: [(&str, Op); 8] = [
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+    ("~", Op::Contains),
+];
+
+impl ItemFilter {
+    /// Checks a stack against this filter. A tag that can't be parsed is treated as empty.
+    pub fn matches(&self, stack: &ItemStack) -> bool {
+        self.matches_tag(&stack.id, &stack.nbt().unwrap_or_default())
+    }
+
+    /// Checks an item id and tag against this filter, for things that aren't [ItemStack]s, like fluids.
+    pub fn matches_tag(&self, id: &str, tag: &Tag) -> bool {
+        self.0.eval(id, tag)
+    }
+}
+
+impl FromStr for ItemFilter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        let expr = or(&mut parser)?;
+        parser.end()?;
+        Ok(ItemFilter(expr))
+    }
+}
+
+impl Expr {
+    fn eval(&self, id: &str, tag: &Tag) -> bool {
+        match self {
+            Expr::Id(pattern) => glob(pattern, id),
+            Expr::Has(path) => tag.lookup(path).is_some(),
+            Expr::Compare(path, op, value) => tag
+                .lookup(path)
+                .is_some_and(|found| op.apply(&found, value)),
+            Expr::Not(expr) => !expr.eval(id, tag),
+            Expr::And(a, b) => a.eval(id, tag) && b.eval(id, tag),
+            Expr::Or(a, b) => a.eval(id, tag) || b.eval(id, tag),
+        }
+    }
+}
+
+impl Op {
+    fn apply(self, found: &Tag, value: &Tag) -> bool {
+        let ordering = || match (found.as_f64(), value.as_f64()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => match (found.as_str(), value.as_str()) {
+                (Some(a), Some(b)) => Some(a.cmp(b)),
+                _ => None,
+            },
+        };
+
+        match self {
+            Op::Eq => found.loose_eq(value),
+            Op::Ne => !found.loose_eq(value),
+            Op::Lt => ordering() == Some(Ordering::Less),
+            Op::Le => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ordering() == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)),
+            Op::Contains => found.contains(value),
+        }
+    }
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+' | ':' | '/' | '*')
+}
+
+/// Consumes `keyword` if it's the next whole word.
+fn keyword(parser: &mut Parser<'_>, keyword: &str) -> bool {
+    let start = parser.position();
+    if parser.eat(keyword) && !parser.rest().starts_with(is_id_char) {
+        return true;
+    }
+
+    parser.reset(start);
+    false
+}
+
+fn or(parser: &mut Parser<'_>) -> Result<Expr, ParseError> {
+    let mut expr = and(parser)?;
+    while keyword(parser, "or") {
+        expr = Expr::Or(Box::new(expr), Box::new(and(parser)?));
+    }
+
+    Ok(expr)
+}
+
+fn and(parser: &mut Parser<'_>) -> Result<Expr, ParseError> {
+    let mut expr = unary(parser)?;
+    while keyword(parser, "and") || keyword(parser, "with") {
+        expr = Expr::And(Box::new(expr), Box::new(unary(parser)?));
+    }
+
+    Ok(expr)
+}
+
+fn unary(parser: &mut Parser<'_>) -> Result<Expr, ParseError> {
+    if keyword(parser, "not") {
+        return Ok(Expr::Not(Box::new(parser.nested(unary)?)));
+    }
+    if parser.eat("(") {
+        let expr = parser.nested(or)?;
+        if !parser.eat(")") {
+            return Err(parser.error("expected ')'"));
+        }
+        return Ok(expr);
+    }
+    if keyword(parser, "has") {
+        parser.skip_whitespace();
+        return Ok(Expr::Has(parser.path()?));
+    }
+
+    atom(parser)
+}
+
+fn atom(parser: &mut Parser<'_>) -> Result<Expr, ParseError> {
+    parser.skip_whitespace();
+    let start = parser.position();
+
+    // a path followed by an operator is a comparison, and anything else has to be an item id.
+    if let Ok(path) = parser.path() {
+        parser.skip_whitespace();
+        if let Some((token, op)) = OPS
+            .iter()
+            .find(|(token, _)| parser.rest().starts_with(token))
+        {
+            parser.eat(token);
+            return Ok(Expr::Compare(path, *op, parser.value()?));
+        }
+    }
+
+    parser.reset(start);
+    let id = parser.take_while(is_id_char);
+    if id.is_empty() || ["and", "or", "with", "not", "has"].contains(&id) {
+        parser.reset(start);
+        return Err(parser.error("expected an item id or a comparison"));
+    }
+
+    Ok(Expr::Id(if id.contains(':') {
+        id.to_owned()
+    } else {
+        format!("minecraft:{}", id)
+    }))
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    // no wildcards at all, so the whole text has to have been the prefix.
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(filter: &str, id: &str, tag: &str) -> bool {
+        let filter: ItemFilter = filter.parse().unwrap();
+        filter.matches_tag(id, &Tag::parse(tag).unwrap())
+    }
+
+    #[test]
+    fn ids_and_wildcards() {
+        assert!(matches("stone", "minecraft:stone", "{}"));
+        assert!(!matches("stone", "create:stone", "{}"));
+        assert!(matches("*_pickaxe", "minecraft:iron_pickaxe", "{}"));
+        assert!(matches("create:*", "create:cogwheel", "{}"));
+        assert!(!matches("*:cog*l", "create:cogwheels", "{}"));
+    }
+
+    #[test]
+    fn comparisons() {
+        let tag = r#"{Damage:12,display:{Name:"Big Sword"},Enchantments:[{id:"minecraft:mending",lvl:1s}]}"#;
+        assert!(matches("Damage < 100", "minecraft:a", tag));
+        assert!(matches("Damage>=12", "minecraft:a", tag));
+        assert!(matches("Damage = 12.0", "minecraft:a", tag));
+        assert!(!matches("Damage != 12", "minecraft:a", tag));
+        assert!(matches(r#"display.Name ~ "Sword""#, "minecraft:a", tag));
+        assert!(matches(
+            r#"Enchantments ~ {id:"minecraft:mending"}"#,
+            "minecraft:a",
+            tag
+        ));
+        // a missing path is false for every operator, != included.
+        assert!(!matches("Missing != 1", "minecraft:a", tag));
+        assert!(matches("has display.Name", "minecraft:a", tag));
+        assert!(!matches("has Enchantments[1]", "minecraft:a", tag));
+    }
+
+    #[test]
+    fn precedence() {
+        // `and` binds tighter than `or`, and `not` tighter than both.
+        assert!(matches(
+            "stone or dirt and has Missing",
+            "minecraft:stone",
+            "{}"
+        ));
+        assert!(!matches(
+            "(stone or dirt) and has Missing",
+            "minecraft:stone",
+            "{}"
+        ));
+        assert!(matches("not dirt and stone", "minecraft:stone", "{}"));
+        assert!(!matches("not (dirt or stone)", "minecraft:stone", "{}"));
+        assert!(matches("stone with not not stone", "minecraft:stone", "{}"));
+    }
+
+    #[test]
+    fn rejects_broken_filters() {
+        for (filter, position) in [
+            ("", 0),
+            ("stone and", 9),
+            ("(stone", 6),
+            ("stone dirt", 6),
+            ("Damage < ", 9),
+            ("has", 3),
+        ] {
+            let err = filter.parse::<ItemFilter>().unwrap_err();
+            assert_eq!(err.position(), position, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        assert!(("not ".repeat(100) + "stone").parse::<ItemFilter>().is_ok());
+        assert!(("(".repeat(100) + "stone" + &")".repeat(100))
+            .parse::<ItemFilter>()
+            .is_ok());
+
+        let err = ("not ".repeat(600) + "stone")
+            .parse::<ItemFilter>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "nested too deeply at position 2051");
+        assert!("(".repeat(100_000).parse::<ItemFilter>().is_err());
+        assert!("not ".repeat(100_000).parse::<ItemFilter>().is_err());
+    }
+}
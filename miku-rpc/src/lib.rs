@@ -1,67 +1,41 @@
 //! A crate implementing the OpenComputers 2 HLApi interface.
 
 mod bus;
-pub use bus::DeviceBus;
-
+pub use bus::{set_raw_mode, DeviceBus};
+#[cfg(feature = "wrappers")]
+mod bound;
+#[cfg(feature = "wrappers")]
+pub use bound::Bound;
+#[cfg(feature = "async")]
+mod async_bus;
+#[cfg(feature = "async")]
+pub use async_bus::AsyncDeviceBus;
+mod prepared;
+pub use prepared::{Fits, FitsArgs, ImplArg, PreparedCall};
+
+/// A small language for matching item stacks by their id and tag.
+pub mod filter;
+/// Support code for the mock devices generated by `#[rpc_interface]`.
+#[cfg(feature = "mock")]
+pub mod mock;
+/// Pathfinding for robots, over a map of the world they learn as they move.
+#[cfg(feature = "wrappers")]
+pub mod navigation;
+/// A model of NBT tags, and a parser for their SNBT representation.
+pub mod nbt;
+/// A job for digging out an area with a robot.
+#[cfg(feature = "wrappers")]
+pub mod quarry;
+/// Helpers for driving robots.
+#[cfg(feature = "wrappers")]
+pub mod robot;
 /// Type definitions for commonly used responses.
 pub mod types;
 /// Wrappers around specific HLApi devices and their methods.
 #[cfg(feature = "wrappers")]
 pub mod wrappers;
-use miniserde_miku::Serialize;
-
-mod response;
-pub use response::*;
-
-#[derive(Serialize)]
-/// A HLApi call, composed of a type and some json serializable data.
-pub struct Call<T: Serialize> {
-    #[serde(rename = "type")]
-    pub msg_type: MessageType,
-    pub data: T,
-}
-
-impl Call<()> {
-    pub fn list() -> Call<()> {
-        Call {
-            msg_type: MessageType::List,
-            data: (),
-        }
-    }
-}
-
-impl Call<&str> {
-    pub fn methods(device_id: &str) -> Call<&str> {
-        Call {
-            msg_type: MessageType::Methods,
-            data: device_id,
-        }
-    }
-}
-
-#[derive(Serialize)]
-/// A HLApi call that invokes a method on a specific device.
-pub struct InvokeCall<'a> {
-    #[serde(rename = "deviceId")]
-    device_id: &'a str,
-    #[serde(rename = "name")]
-    method_name: &'a str,
-    parameters: &'a [&'a dyn Serialize],
-}
 
-impl Call<InvokeCall<'_>> {
-    pub fn invoke<'a>(
-        device_id: &'a str,
-        method_name: &'a str,
-        parameters: &'a [&'a dyn Serialize],
-    ) -> Call<InvokeCall<'a>> {
-        Call {
-            msg_type: MessageType::Invoke,
-            data: InvokeCall {
-                device_id,
-                method_name,
-                parameters,
-            },
-        }
-    }
-}
+pub use miku_codec::{
+    Call, EncodeArg, EncodeArgs, InvokeCall, InvokeWriter, MessageType, ParseEnumError, RPCError,
+    RPCResult, Response, ResultValue,
+};
@@ -0,0 +1,95 @@
+//! Every trait in [crate::wrappers] gets a `Mock` type, like [crate::wrappers::MockRobotInterface], which implements it with closures instead of HLApi calls. They still take a bus, so use a [DeviceBus::detached] one.
+//!
+//! ```
+//! # use miku_rpc::wrappers::{MockRobotInterface, RobotInterface};
+//! # use miku_rpc::types::RobotActionResult;
+//! # use miku_rpc::DeviceBus;
+//! let mut robot = MockRobotInterface::new("robot");
+//! robot.expect_get_selected_slot(|| Ok(3)).times(1);
+//! robot.expect_get_last_action_id(|| Ok(7));
+//! robot.expect_try_get_action_result(|id| {
+//!     assert_eq!(id, 7);
+//!     Ok(Some(RobotActionResult::Success))
+//! });
+//!
+//! let mut bus = DeviceBus::detached().unwrap();
+//! assert_eq!(robot.get_selected_slot(&mut bus).unwrap(), 3);
+//! let id = robot.get_last_action_id(&mut bus).unwrap();
+//! assert!(robot.wait_for_action(&mut bus, id).unwrap());
+//! ```
+//!
+//! Calling a method nobody set a closure for panics, and so does dropping a mock with an expectation that wasn't called as many [times](Expectation::times) as it should have been.
+#[cfg(doc)]
+use crate::DeviceBus;
+use std::cell::{Cell, RefCell};
+
+/// The closure a mock answers one method with, along with how many times it was called.
+pub struct Expectation<F: ?Sized> {
+    name: &'static str,
+    handler: RefCell<Option<Box<F>>>,
+    calls: Cell<usize>,
+    times: Option<usize>,
+}
+
+impl<F: ?Sized> Expectation<F> {
+    #[doc(hidden)]
+    pub fn new(name: &'static str) -> Expectation<F> {
+        Expectation {
+            name,
+            handler: RefCell::new(None),
+            calls: Cell::new(0),
+            times: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn set(&mut self, handler: Box<F>) -> &mut Self {
+        *self.handler.get_mut() = Some(handler);
+        self.calls.set(0);
+        self
+    }
+
+    /// Makes the method expected to be called exactly `n` times. It panics on the call after that, and the mock panics when dropped if it was called less.
+    pub fn times(&mut self, n: usize) -> &mut Self {
+        self.times = Some(n);
+        self
+    }
+
+    /// Returns how many times the method was called since its closure was set.
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+
+    #[doc(hidden)]
+    pub fn call<R>(&self, f: impl FnOnce(&mut F) -> R) -> R {
+        let calls = self.calls.get() + 1;
+        if let Some(times) = self.times {
+            assert!(
+                calls <= times,
+                "{} was called more than the {} times it was expected to be",
+                self.name,
+                times
+            );
+        }
+        self.calls.set(calls);
+
+        let mut handler = self.handler.borrow_mut();
+        match handler.as_mut() {
+            Some(handler) => f(handler),
+            None => panic!("unexpected call to {}", self.name),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn verify(&self) {
+        if let Some(times) = self.times {
+            assert!(
+                self.calls.get() == times,
+                "{} was called {} times, but was expected to be called {} times",
+                self.name,
+                self.calls.get(),
+                times
+            );
+        }
+    }
+}
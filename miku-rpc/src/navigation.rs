@@ -0,0 +1,611 @@
+use crate::robot::TrackedRobot;
+use crate::types::{BlockPos, Cuboid, Direction};
+use crate::wrappers::{BlockOperationsInterface, BlockOperationsModule, RobotInterface};
+use crate::DeviceBus;
+use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BinaryHeap;
+use std::io;
+
+/// What a robot has learned about a block.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Cell {
+    /// The robot has been here, so it can go here again.
+    Free,
+    /// A move into this block failed. Digging might clear it.
+    Blocked,
+    /// Digging this block failed with a working tool, and the robot still couldn't move into it, like with bedrock.
+    Unbreakable,
+}
+
+/// A sparse map of the blocks a robot has learned about. Blocks it hasn't are assumed to be free until it finds out otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct VoxelMap {
+    cells: HashMap<BlockPos, Cell>,
+}
+
+impl VoxelMap {
+    pub fn new() -> VoxelMap {
+        VoxelMap::default()
+    }
+
+    pub fn get(&self, pos: BlockPos) -> Option<Cell> {
+        self.cells.get(&pos).copied()
+    }
+
+    pub fn set(&mut self, pos: BlockPos, cell: Cell) {
+        self.cells.insert(pos, cell);
+    }
+
+    /// Forgets what's known about `pos`, like after the world around it changed.
+    pub fn forget(&mut self, pos: BlockPos) -> Option<Cell> {
+        self.cells.remove(&pos)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    /// Returns the number of known blocks.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockPos, Cell)> + '_ {
+        self.cells.iter().map(|(&pos, &cell)| (pos, cell))
+    }
+}
+
+/// How a [Navigator::go_to] ended.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum NavigationOutcome {
+    Arrived,
+    /// Every route to the target within the search area is known to be blocked.
+    Unreachable,
+    /// The robot kept running into new obstacles, and replanned as many times as it was allowed to.
+    GaveUp,
+    /// Digging kept failing on different blocks while the tool still had durability left, which says more about the tool than about the blocks.
+    /// The blocks stay [Cell::Blocked] instead of being marked unbreakable.
+    ToolBroken,
+}
+
+impl NavigationOutcome {
+    pub fn is_arrived(&self) -> bool {
+        matches!(self, NavigationOutcome::Arrived)
+    }
+}
+
+/// Moves a [TrackedRobot] around with A*, learning a [VoxelMap] of where it can and can't go as it does, and planning a new route whenever the current one turns out to be blocked.
+///
+/// Given a [BlockOperationsInterface] with [Navigator::dig_with], it digs through blocked cells instead of only going around them, when that's cheaper.
+///
+/// ```no_run
+/// # use miku_rpc::navigation::Navigator;
+/// # use miku_rpc::robot::TrackedRobot;
+/// # use miku_rpc::types::BlockPos;
+/// # use miku_rpc::wrappers::{BlockOperationsModule, Robot};
+/// # use miku_rpc::DeviceBus;
+/// # fn main() -> std::io::Result<()> {
+/// let mut bus = DeviceBus::new("/dev/hvc0")?;
+/// let robot: Robot = bus.wrap()?.expect("not a robot!");
+/// let module: BlockOperationsModule = bus.wrap()?.expect("no block operations module!");
+///
+/// let mut navigator = Navigator::new(TrackedRobot::new(&robot, Default::default())).dig_with(&module);
+/// let outcome = navigator.go_to(&mut bus, BlockPos::new(12, -4, 30))?;
+/// println!("{:?} at {}", outcome, navigator.robot().pose());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Navigator<'r, R: ?Sized, D: ?Sized = BlockOperationsModule> {
+    robot: TrackedRobot<'r, R>,
+    map: VoxelMap,
+    digger: Option<&'r D>,
+    dig_cost: u32,
+    margin: i32,
+    bounds: Option<Cuboid>,
+    dig_bounds: Option<Cuboid>,
+    max_replans: usize,
+    max_failed_digs: usize,
+    // blocks that couldn't be dug during the current go_to().
+    failed_digs: Vec<BlockPos>,
+}
+
+impl<'r, R: RobotInterface + ?Sized> Navigator<'r, R> {
+    pub fn new(robot: TrackedRobot<'r, R>) -> Navigator<'r, R> {
+        Navigator {
+            robot,
+            map: VoxelMap::new(),
+            digger: None,
+            dig_cost: 4,
+            margin: 16,
+            bounds: None,
+            dig_bounds: None,
+            max_replans: 64,
+            max_failed_digs: 3,
+            failed_digs: Vec::new(),
+        }
+    }
+}
+
+impl<'r, R: RobotInterface + ?Sized, D: BlockOperationsInterface + ?Sized> Navigator<'r, R, D> {
+    /// Allows digging through blocked cells with `digger`, which has to be installed in the same robot.
+    pub fn dig_with<E: BlockOperationsInterface + ?Sized>(
+        self,
+        digger: &'r E,
+    ) -> Navigator<'r, R, E> {
+        Navigator {
+            robot: self.robot,
+            map: self.map,
+            digger: Some(digger),
+            dig_cost: self.dig_cost,
+            margin: self.margin,
+            bounds: self.bounds,
+            dig_bounds: self.dig_bounds,
+            max_replans: self.max_replans,
+            max_failed_digs: self.max_failed_digs,
+            failed_digs: self.failed_digs,
+        }
+    }
+
+    /// Sets how many moves digging a block is worth, on top of moving into it. Defaults to 4.
+    pub fn dig_cost(mut self, dig_cost: u32) -> Self {
+        self.dig_cost = dig_cost;
+        self
+    }
+
+    /// Sets how far around the start and the target routes can stray. Defaults to 16 blocks.
+    pub fn margin(mut self, margin: i32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Keeps routes inside `bounds`, on top of the margin.
+    pub fn bounds(mut self, bounds: Cuboid) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Only digs blocks inside `bounds`, and goes around blocked cells outside of them, like to keep the robot from digging up what's around the area it works in.
+    pub fn dig_within(mut self, bounds: Cuboid) -> Self {
+        self.dig_bounds = Some(bounds);
+        self
+    }
+
+    /// Sets how many times a single [Navigator::go_to] can plan a new route. Defaults to 64.
+    pub fn max_replans(mut self, max_replans: usize) -> Self {
+        self.max_replans = max_replans;
+        self
+    }
+
+    /// Sets on how many different blocks digging can fail during a single [Navigator::go_to] before it stops with [NavigationOutcome::ToolBroken]. Defaults to 3.
+    pub fn max_failed_digs(mut self, max_failed_digs: usize) -> Self {
+        self.max_failed_digs = max_failed_digs;
+        self
+    }
+
+    pub fn with_map(mut self, map: VoxelMap) -> Self {
+        self.map = map;
+        self
+    }
+
+    pub fn robot(&self) -> &TrackedRobot<'r, R> {
+        &self.robot
+    }
+
+    pub fn robot_mut(&mut self) -> &mut TrackedRobot<'r, R> {
+        &mut self.robot
+    }
+
+    pub fn map(&self) -> &VoxelMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut VoxelMap {
+        &mut self.map
+    }
+
+    pub fn into_inner(self) -> (TrackedRobot<'r, R>, VoxelMap) {
+        (self.robot, self.map)
+    }
+
+    /// Moves the robot to `target`, replanning around (or through) obstacles as it finds them.
+    pub fn go_to(
+        &mut self,
+        bus: &mut DeviceBus,
+        target: BlockPos,
+    ) -> io::Result<NavigationOutcome> {
+        self.map.set(self.robot.position(), Cell::Free);
+        self.failed_digs.clear();
+
+        for _ in 0..=self.max_replans {
+            let Some(path) = self.plan(self.robot.position(), target) else {
+                return Ok(NavigationOutcome::Unreachable);
+            };
+            if self.follow(bus, &path)? {
+                return Ok(NavigationOutcome::Arrived);
+            }
+            if self.failed_digs.len() >= self.max_failed_digs.max(1) {
+                for &pos in &self.failed_digs {
+                    self.map.set(pos, Cell::Blocked);
+                }
+                return Ok(NavigationOutcome::ToolBroken);
+            }
+        }
+
+        Ok(NavigationOutcome::GaveUp)
+    }
+
+    /// Plans a route from `from` to `target` with what's known so far. The route includes `target` but not `from`.
+    pub fn plan(&self, from: BlockPos, target: BlockPos) -> Option<Vec<BlockPos>> {
+        let area = self.search_area(from, target)?;
+        if !area.contains(target) {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::new();
+        let mut parents = HashMap::new();
+        open.push(Node::new(from, 0, from.manhattan_distance(target)));
+        costs.insert(from, 0);
+
+        while let Some(Node { pos, cost, .. }) = open.pop() {
+            if pos == target {
+                let mut path = Vec::new();
+                let mut pos = target;
+                while pos != from {
+                    path.push(pos);
+                    pos = parents[&pos];
+                }
+                path.reverse();
+                return Some(path);
+            }
+            // a cheaper way here was found after this node was queued.
+            if costs.get(&pos).is_some_and(|&best| cost > best) {
+                continue;
+            }
+
+            for direction in Direction::ALL {
+                let next = pos.neighbor(direction);
+                if !area.contains(next) {
+                    continue;
+                }
+                let Some(step) = self.step_cost(next) else {
+                    continue;
+                };
+
+                let cost = cost + step;
+                match costs.entry(next) {
+                    Entry::Occupied(e) if *e.get() <= cost => continue,
+                    Entry::Occupied(mut e) => {
+                        e.insert(cost);
+                    }
+                    Entry::Vacant(e) => {
+                        e.insert(cost);
+                    }
+                }
+                parents.insert(next, pos);
+                open.push(Node::new(next, cost, next.manhattan_distance(target)));
+            }
+        }
+
+        None
+    }
+
+    fn step_cost(&self, pos: BlockPos) -> Option<u32> {
+        match self.map.get(pos) {
+            None | Some(Cell::Free) => Some(1),
+            Some(Cell::Blocked) if self.can_dig(pos) => Some(1 + self.dig_cost),
+            Some(Cell::Blocked) | Some(Cell::Unbreakable) => None,
+        }
+    }
+
+    fn can_dig(&self, pos: BlockPos) -> bool {
+        self.digger.is_some() && self.dig_bounds.is_none_or(|bounds| bounds.contains(pos))
+    }
+
+    fn search_area(&self, from: BlockPos, target: BlockPos) -> Option<Cuboid> {
+        let margin = BlockPos::new(self.margin, self.margin, self.margin);
+        let around = Cuboid::new(from, target);
+        let area = Cuboid::new(around.min - margin, around.max + margin);
+        let Some(bounds) = self.bounds else {
+            return Some(area);
+        };
+
+        let min = BlockPos::new(
+            area.min.x.max(bounds.min.x),
+            area.min.y.max(bounds.min.y),
+            area.min.z.max(bounds.min.z),
+        );
+        let max = BlockPos::new(
+            area.max.x.min(bounds.max.x),
+            area.max.y.min(bounds.max.y),
+            area.max.z.min(bounds.max.z),
+        );
+        (min.x <= max.x && min.y <= max.y && min.z <= max.z).then_some(Cuboid { min, max })
+    }
+
+    // walks along `path`, returning false as soon as something gets in the way.
+    fn follow(&mut self, bus: &mut DeviceBus, path: &[BlockPos]) -> io::Result<bool> {
+        for &next in path {
+            let here = self.robot.position();
+            let Some(direction) = Direction::ALL
+                .into_iter()
+                .find(|&d| here.neighbor(d) == next)
+            else {
+                return Ok(false);
+            };
+
+            if self.map.get(next) == Some(Cell::Blocked) {
+                match self.try_dig(bus, direction)? {
+                    Some(true) => {}
+                    Some(false) => {
+                        // excavate fails on air and on entities too, so a block only counts as unbreakable if it still stops the robot, and digging it fails again.
+                        if self.robot.step(bus, direction)? {
+                            self.map.set(next, Cell::Free);
+                            continue;
+                        }
+                        match self.try_dig(bus, direction)? {
+                            Some(true) => {}
+                            Some(false) => {
+                                self.map.set(next, Cell::Unbreakable);
+                                if !self.failed_digs.contains(&next) {
+                                    self.failed_digs.push(next);
+                                }
+                                return Ok(false);
+                            }
+                            None => return Ok(false),
+                        }
+                    }
+                    None => return Ok(false),
+                }
+            }
+            if !self.robot.step(bus, direction)? {
+                self.map.set(next, Cell::Blocked);
+                return Ok(false);
+            }
+            self.map.set(next, Cell::Free);
+        }
+
+        Ok(true)
+    }
+
+    /// Digs the block next to the robot in `direction`, turning to face it first if it's horizontal.
+    /// Returns false without doing anything if there's nothing to dig with, or the block is outside of [Navigator::dig_within]'s bounds.
+    pub fn dig(&mut self, bus: &mut DeviceBus, direction: Direction) -> io::Result<bool> {
+        Ok(self.try_dig(bus, direction)? == Some(true))
+    }
+
+    // same as dig(), but returns None when the block couldn't be tried at all: it can't be dug, a turn failed, or the tool is worn out.
+    // only Some(false) says anything about the block itself.
+    fn try_dig(&mut self, bus: &mut DeviceBus, direction: Direction) -> io::Result<Option<bool>> {
+        let Some(digger) = self.digger else {
+            return Ok(None);
+        };
+        if !self.can_dig(self.robot.position().neighbor(direction)) {
+            return Ok(None);
+        }
+        let Some(side) = self.robot.side_towards(bus, direction)? else {
+            return Ok(None);
+        };
+
+        if digger.excavate(bus, side)? {
+            return Ok(Some(true));
+        }
+        Ok((digger.durability(bus)? > 0).then_some(false))
+    }
+}
+
+#[derive(PartialEq, Eq)]
+struct Node {
+    pos: BlockPos,
+    cost: u32,
+    estimate: u32,
+}
+
+impl Node {
+    fn new(pos: BlockPos, cost: u32, heuristic: u32) -> Node {
+        Node {
+            pos,
+            cost,
+            estimate: cost + heuristic,
+        }
+    }
+}
+
+// BinaryHeap pops the biggest node, so the cheapest estimate has to compare as the biggest. Ties go to the node closest to the target.
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then(self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::{RPCDevice, Robot};
+    use std::sync::LazyLock;
+
+    static ROBOT: LazyLock<Robot> = LazyLock::new(|| Robot::from_id(String::new()));
+    static MODULE: LazyLock<BlockOperationsModule> =
+        LazyLock::new(|| BlockOperationsModule::from_id(String::new()));
+
+    fn at(x: i32, z: i32) -> BlockPos {
+        BlockPos::new(x, 0, z)
+    }
+
+    // a navigator kept to the y = 0 plane, with a wall of `cell`s from x = -2 to 2 two blocks north of the origin.
+    fn walled_in<'r>(robot: &'r Robot, cell: Cell) -> Navigator<'r, Robot> {
+        let mut map = VoxelMap::new();
+        for x in -2..=2 {
+            map.set(at(x, -2), cell);
+        }
+
+        Navigator::new(TrackedRobot::new(robot, Default::default()))
+            .bounds(Cuboid::new(at(-5, -5), at(5, 5)))
+            .with_map(map)
+    }
+
+    fn assert_connected(from: BlockPos, path: &[BlockPos]) {
+        let mut here = from;
+        for &next in path {
+            assert_eq!(here.manhattan_distance(next), 1, "{} to {}", here, next);
+            here = next;
+        }
+    }
+
+    #[test]
+    fn detours_around_walls() {
+        let navigator = walled_in(&ROBOT, Cell::Blocked);
+        let path = navigator.plan(at(0, 0), at(0, -4)).unwrap();
+
+        assert_connected(at(0, 0), &path);
+        assert_eq!(path.last(), Some(&at(0, -4)));
+        // three blocks over to the end of the wall, four past it, and three back.
+        assert_eq!(path.len(), 10);
+        assert!(path.iter().all(|pos| navigator.map().get(*pos).is_none()));
+    }
+
+    #[test]
+    fn digs_through_when_its_cheaper() {
+        let navigator = walled_in(&ROBOT, Cell::Blocked).dig_with(&*MODULE);
+        let path = navigator.plan(at(0, 0), at(0, -4)).unwrap();
+        assert_eq!(path, [at(0, -1), at(0, -2), at(0, -3), at(0, -4)]);
+
+        // digging for 7 more moves costs more than the detour's 6 extra ones.
+        let navigator = navigator.dig_cost(7);
+        assert_eq!(navigator.plan(at(0, 0), at(0, -4)).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn only_digs_within_its_bounds() {
+        let navigator = walled_in(&ROBOT, Cell::Blocked)
+            .dig_with(&*MODULE)
+            .dig_within(Cuboid::new(at(-5, -5), at(5, -3)));
+        assert_eq!(navigator.plan(at(0, 0), at(0, -4)).unwrap().len(), 10);
+
+        let navigator = navigator.dig_within(Cuboid::new(at(0, -5), at(0, 0)));
+        assert_eq!(navigator.plan(at(0, 0), at(0, -4)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn unbreakable_cells_arent_dug() {
+        let mut navigator = walled_in(&ROBOT, Cell::Unbreakable).dig_with(&*MODULE);
+        let path = navigator.plan(at(0, 0), at(0, -4)).unwrap();
+        assert_eq!(path.len(), 10);
+
+        // routes can't leave the bounds, so a wall across them can't be passed, and targets outside them can't be reached.
+        for x in -5..=5 {
+            navigator.map_mut().set(at(x, -2), Cell::Unbreakable);
+        }
+        assert_eq!(navigator.plan(at(0, 0), at(0, -4)), None);
+        assert_eq!(navigator.plan(at(0, 0), at(0, 6)), None);
+    }
+
+    // a bus for a robot whose moves all fail unless `moves`, with a block operations module that can't dig anything and has `durability` left.
+    fn stuck_digger(durability: i32, moves: bool) -> DeviceBus {
+        let mut last_action = String::new();
+        DeviceBus::serve(move |request| {
+            let name = request.split(r#""name":""#).nth(1).unwrap();
+            let name = &name[..name.find('"').unwrap()];
+            let data = match name {
+                "durability" => durability.to_string(),
+                "move" | "turn" => {
+                    last_action = name.to_owned();
+                    "true".to_owned()
+                }
+                "getLastActionId" => "1".to_owned(),
+                "getActionResult" if last_action == "move" && !moves => r#""FAILURE""#.to_owned(),
+                "getActionResult" => r#""SUCCESS""#.to_owned(),
+                _ => "false".to_owned(),
+            };
+            format!(r#"{{"type":"result","data":{}}}"#, data)
+        })
+        .unwrap()
+    }
+
+    // a robot under a blocked cell it can't dig.
+    fn dig_up(durability: i32, moves: bool) -> (NavigationOutcome, Option<Cell>) {
+        let mut bus = stuck_digger(durability, moves);
+        let above = BlockPos::new(0, 1, 0);
+        let mut navigator = Navigator::new(TrackedRobot::new(&*ROBOT, Default::default()))
+            .dig_with(&*MODULE)
+            .bounds(Cuboid::new(BlockPos::ORIGIN, above))
+            .max_replans(2);
+        navigator.map_mut().set(above, Cell::Blocked);
+
+        let outcome = navigator.go_to(&mut bus, above).unwrap();
+        (outcome, navigator.map().get(above))
+    }
+
+    #[test]
+    fn only_a_working_tool_finds_unbreakable_blocks() {
+        assert_eq!(
+            dig_up(10, false),
+            (NavigationOutcome::Unreachable, Some(Cell::Unbreakable))
+        );
+        // a worn out tool says nothing about the block.
+        assert_eq!(
+            dig_up(0, false),
+            (NavigationOutcome::GaveUp, Some(Cell::Blocked))
+        );
+    }
+
+    #[test]
+    fn failed_digs_into_air_dont_count() {
+        assert_eq!(
+            dig_up(10, true),
+            (NavigationOutcome::Arrived, Some(Cell::Free))
+        );
+    }
+
+    // a robot boxed in by a ring of blocked cells it can't dig, with its target outside of them.
+    fn boxed_in(max_failed_digs: usize) -> (NavigationOutcome, Navigator<'static, Robot>) {
+        let mut bus = stuck_digger(10, false);
+        let mut map = VoxelMap::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                if (x, z) != (0, 0) {
+                    map.set(at(x, z), Cell::Blocked);
+                }
+            }
+        }
+
+        let mut navigator = Navigator::new(TrackedRobot::new(&*ROBOT, Default::default()))
+            .dig_with(&*MODULE)
+            .bounds(Cuboid::new(at(-2, -2), at(2, 2)))
+            .max_failed_digs(max_failed_digs)
+            .with_map(map);
+        let outcome = navigator.go_to(&mut bus, at(2, 2)).unwrap();
+        (outcome, navigator)
+    }
+
+    fn count(navigator: &Navigator<'_, Robot>, cell: Cell) -> usize {
+        navigator.map().iter().filter(|&(_, c)| c == cell).count()
+    }
+
+    #[test]
+    fn stops_when_digging_keeps_failing() {
+        let (outcome, navigator) = boxed_in(3);
+        assert_eq!(outcome, NavigationOutcome::ToolBroken);
+        assert_eq!(count(&navigator, Cell::Unbreakable), 0);
+        assert_eq!(count(&navigator, Cell::Blocked), 8);
+
+        // with room for every one of the robot's neighbors to fail, it runs out of routes first.
+        let (outcome, navigator) = boxed_in(5);
+        assert_eq!(outcome, NavigationOutcome::Unreachable);
+        assert_eq!(count(&navigator, Cell::Unbreakable), 4);
+    }
+}
@@ -0,0 +1,730 @@
+use miniserde_miku::json::{Number, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A NBT value, like the ones in item and fluid tags.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    ByteArray(Vec<i8>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Default for Tag {
+    fn default() -> Self {
+        Tag::Compound(BTreeMap::new())
+    }
+}
+
+impl Tag {
+    /// Parses a tag from its SNBT representation, like `{Damage:12,Unbreakable:1b}`.
+    pub fn parse(snbt: &str) -> Result<Tag, ParseError> {
+        let mut parser = Parser::new(snbt);
+        let tag = parser.value()?;
+        parser.end()?;
+        Ok(tag)
+    }
+
+    /// Converts a tag from the json OC2 describes tags with.
+    ///
+    /// The json doesn't say which type each number had, so integers become [Tag::Int] or [Tag::Long] depending on their size, and everything else becomes a [Tag::Double]. Null, which NBT has no equivalent for, becomes an empty compound.
+    pub fn from_json(value: &Value) -> Tag {
+        match value {
+            Value::Null => Tag::default(),
+            Value::Bool(b) => Tag::Byte(*b as i8),
+            Value::Number(Number::U64(n)) => i32::try_from(*n)
+                .map(Tag::Int)
+                .or_else(|_| i64::try_from(*n).map(Tag::Long))
+                .unwrap_or(Tag::Double(*n as f64)),
+            Value::Number(Number::I64(n)) => i32::try_from(*n).map_or(Tag::Long(*n), Tag::Int),
+            Value::Number(Number::F64(n)) => Tag::Double(*n),
+            Value::String(s) => Tag::String(s.clone()),
+            Value::Array(values) => Tag::List(values.iter().map(Tag::from_json).collect()),
+            Value::Object(object) => Tag::Compound(
+                object
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Tag::from_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Gets the value of a key, if this is a compound that has it.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    /// Follows a path like `display.Lore[0]` down from this tag.
+    ///
+    /// Elements of typed arrays aren't stored as tags, which is why this can return an owned one.
+    pub fn lookup(&self, path: &TagPath) -> Option<Cow<'_, Tag>> {
+        let mut tag = Cow::Borrowed(self);
+        for segment in &path.0 {
+            tag = match (tag, segment) {
+                (Cow::Borrowed(tag), PathSegment::Key(key)) => Cow::Borrowed(tag.get(key)?),
+                (Cow::Borrowed(tag), PathSegment::Index(i)) => tag.index(*i)?,
+                _ => return None,
+            };
+        }
+
+        Some(tag)
+    }
+
+    fn index(&self, i: usize) -> Option<Cow<'_, Tag>> {
+        match self {
+            Tag::List(tags) => tags.get(i).map(Cow::Borrowed),
+            Tag::ByteArray(values) => values.get(i).map(|v| Cow::Owned(Tag::Byte(*v))),
+            Tag::IntArray(values) => values.get(i).map(|v| Cow::Owned(Tag::Int(*v))),
+            Tag::LongArray(values) => values.get(i).map(|v| Cow::Owned(Tag::Long(*v))),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an integer tag.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(n) => Some(n as i64),
+            Tag::Short(n) => Some(n as i64),
+            Tag::Int(n) => Some(n as i64),
+            Tag::Long(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any numeric tag.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(n) => Some(n as f64),
+            Tag::Double(n) => Some(n),
+            _ => self.as_i64().map(|n| n as f64),
+        }
+    }
+
+    /// Returns the value of a string tag.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Compares two tags without caring about the types of numbers, since those get lost going through json.
+    pub fn loose_eq(&self, other: &Tag) -> bool {
+        match (self, other) {
+            (Tag::String(a), Tag::String(b)) => a == b,
+            (Tag::Compound(a), Tag::Compound(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, a)| b.get(key).is_some_and(|b| a.loose_eq(b)))
+            }
+            (a, b) => match (a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => match (a.elements(), b.elements()) {
+                    (Some(a), Some(b)) => {
+                        a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| a.loose_eq(b))
+                    }
+                    _ => false,
+                },
+            },
+        }
+    }
+
+    /// Whether this tag contains `pattern`: a substring of a string, an element of a list, or a subset of a compound's entries.
+    pub fn contains(&self, pattern: &Tag) -> bool {
+        match (self, pattern) {
+            (Tag::String(s), Tag::String(pattern)) => s.contains(pattern.as_str()),
+            (Tag::Compound(entries), Tag::Compound(pattern)) => pattern.iter().all(|(key, p)| {
+                entries
+                    .get(key)
+                    .is_some_and(|tag| tag.loose_eq(p) || tag.contains(p))
+            }),
+            _ => self.elements().is_some_and(|elements| {
+                elements
+                    .iter()
+                    .any(|tag| tag.loose_eq(pattern) || tag.contains(pattern))
+            }),
+        }
+    }
+
+    fn elements(&self) -> Option<Vec<Cow<'_, Tag>>> {
+        let len = match self {
+            Tag::List(tags) => tags.len(),
+            Tag::ByteArray(values) => values.len(),
+            Tag::IntArray(values) => values.len(),
+            Tag::LongArray(values) => values.len(),
+            _ => return None,
+        };
+
+        (0..len).map(|i| self.index(i)).collect()
+    }
+}
+
+impl FromStr for Tag {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tag::parse(s)
+    }
+}
+
+/// Writes the tag as SNBT.
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn array<T: fmt::Display>(
+            f: &mut fmt::Formatter<'_>,
+            prefix: char,
+            suffix: &str,
+            values: &[T],
+        ) -> fmt::Result {
+            write!(f, "[{};", prefix)?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}{}", value, suffix)?;
+            }
+            f.write_str("]")
+        }
+
+        match self {
+            Tag::Byte(n) => write!(f, "{}b", n),
+            Tag::Short(n) => write!(f, "{}s", n),
+            Tag::Int(n) => write!(f, "{}", n),
+            Tag::Long(n) => write!(f, "{}L", n),
+            Tag::Float(n) => write!(f, "{:?}f", n),
+            Tag::Double(n) => write!(f, "{:?}d", n),
+            Tag::String(s) => write_quoted(f, s),
+            Tag::List(tags) => {
+                f.write_str("[")?;
+                for (i, tag) in tags.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", tag)?;
+                }
+                f.write_str("]")
+            }
+            Tag::Compound(entries) => {
+                f.write_str("{")?;
+                for (i, (key, tag)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_key(f, key)?;
+                    write!(f, ":{}", tag)?;
+                }
+                f.write_str("}")
+            }
+            Tag::ByteArray(values) => array(f, 'B', "b", values),
+            Tag::IntArray(values) => array(f, 'I', "", values),
+            Tag::LongArray(values) => array(f, 'L', "L", values),
+        }
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    f.write_str("\"")
+}
+
+fn write_key(f: &mut fmt::Formatter<'_>, key: &str) -> fmt::Result {
+    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+        f.write_str(key)
+    } else {
+        write_quoted(f, key)
+    }
+}
+
+/// A path to a value nested inside a tag, like `display.Name` or `Enchantments[0].id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagPath(Vec<PathSegment>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl FromStr for TagPath {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        let path = parser.path()?;
+        parser.end()?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for TagPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    // dots are allowed in unquoted keys, but not in a path's.
+                    if key.contains('.') {
+                        write_quoted(f, key)?;
+                    } else {
+                        write_key(f, key)?;
+                    }
+                }
+                PathSegment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An error for malformed SNBT, tag paths, or item filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    position: usize,
+    message: &'static str,
+}
+
+impl ParseError {
+    /// Returns the byte offset the error was found at.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+/// How deeply lists, compounds and filter expressions can be nested, so that hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+/// A cursor over SNBT, shared with the item filter parser, which embeds SNBT values.
+pub(crate) struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(src: &'a str) -> Parser<'a> {
+        Parser {
+            src,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            position: self.pos,
+            message,
+        }
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn reset(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub(crate) fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips whitespace, then consumes `token` if it's next.
+    pub(crate) fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str, message: &'static str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    pub(crate) fn end(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.rest().is_empty() {
+            Ok(())
+        } else {
+            Err(self.error("unexpected trailing input"))
+        }
+    }
+
+    /// Consumes characters for as long as `f` accepts them.
+    pub(crate) fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Runs `f` one level of nesting deeper, failing once [MAX_DEPTH] is reached.
+    pub(crate) fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    pub(crate) fn value(&mut self) -> Result<Tag, ParseError> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('{') => self.nested(Parser::compound),
+            Some('[') => self.nested(Parser::list),
+            Some('"' | '\'') => Ok(Tag::String(self.quoted()?)),
+            _ => {
+                let start = self.pos;
+                let token = self.take_while(is_unquoted_char);
+                if token.is_empty() {
+                    self.reset(start);
+                    return Err(self.error("expected a value"));
+                }
+
+                Ok(classify(token))
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Result<Tag, ParseError> {
+        self.expect("{", "expected '{'")?;
+        let mut entries = BTreeMap::new();
+        if self.eat("}") {
+            return Ok(Tag::Compound(entries));
+        }
+
+        loop {
+            let key = self.key()?;
+            self.expect(":", "expected ':' after key")?;
+            entries.insert(key, self.value()?);
+
+            if self.eat("}") {
+                return Ok(Tag::Compound(entries));
+            }
+            self.expect(",", "expected ',' or '}'")?;
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        if self.rest().starts_with(['"', '\'']) {
+            return self.quoted();
+        }
+
+        match self.take_while(is_unquoted_char) {
+            "" => Err(self.error("expected a key")),
+            key => Ok(key.to_owned()),
+        }
+    }
+
+    fn list(&mut self) -> Result<Tag, ParseError> {
+        self.expect("[", "expected '['")?;
+        for (prefix, array) in [("B;", 'B'), ("I;", 'I'), ("L;", 'L')] {
+            if self.rest().starts_with(prefix) {
+                self.pos += prefix.len();
+                return self.array(array);
+            }
+        }
+
+        let mut tags = Vec::new();
+        if self.eat("]") {
+            return Ok(Tag::List(tags));
+        }
+
+        loop {
+            tags.push(self.value()?);
+
+            if self.eat("]") {
+                return Ok(Tag::List(tags));
+            }
+            self.expect(",", "expected ',' or ']'")?;
+        }
+    }
+
+    fn array(&mut self, kind: char) -> Result<Tag, ParseError> {
+        let mut values = Vec::new();
+        if !self.eat("]") {
+            loop {
+                let start = self.pos;
+                let value = self.value()?.as_i64();
+                values.push(value.ok_or_else(|| {
+                    self.reset(start);
+                    self.error("expected an integer")
+                })?);
+
+                if self.eat("]") {
+                    break;
+                }
+                self.expect(",", "expected ',' or ']'")?;
+            }
+        }
+
+        // like minecraft, out of range elements wrap around.
+        Ok(match kind {
+            'B' => Tag::ByteArray(values.into_iter().map(|v| v as i8).collect()),
+            'I' => Tag::IntArray(values.into_iter().map(|v| v as i32).collect()),
+            _ => Tag::LongArray(values),
+        })
+    }
+
+    pub(crate) fn quoted(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        let quote = match self.rest().chars().next() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err(self.error("expected a quoted string")),
+        };
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut out = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c)) if c == quote || c == '\\' => out.push(c),
+                    _ => {
+                        self.pos += i;
+                        return Err(self.error("invalid escape"));
+                    }
+                },
+                c if c == quote => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                c => out.push(c),
+            }
+        }
+
+        self.reset(start);
+        Err(self.error("unterminated string"))
+    }
+
+    pub(crate) fn path(&mut self) -> Result<TagPath, ParseError> {
+        let mut segments = vec![PathSegment::Key(self.path_key()?)];
+        loop {
+            if self.rest().starts_with('.') {
+                self.pos += 1;
+                segments.push(PathSegment::Key(self.path_key()?));
+            } else if self.rest().starts_with('[') {
+                self.pos += 1;
+                let index = self.take_while(|c| c.is_ascii_digit());
+                let index = index.parse().map_err(|_| self.error("expected an index"))?;
+                segments.push(PathSegment::Index(index));
+                self.expect("]", "expected ']'")?;
+            } else {
+                return Ok(TagPath(segments));
+            }
+        }
+    }
+
+    fn path_key(&mut self) -> Result<String, ParseError> {
+        if self.rest().starts_with(['"', '\'']) {
+            return self.quoted();
+        }
+
+        match self.take_while(|c| c != '.' && is_unquoted_char(c)) {
+            "" => Err(self.error("expected a key")),
+            key => Ok(key.to_owned()),
+        }
+    }
+}
+
+/// Works out the type of an unquoted SNBT value, the same way minecraft does.
+fn classify(token: &str) -> Tag {
+    if token.eq_ignore_ascii_case("true") {
+        return Tag::Byte(1);
+    }
+    if token.eq_ignore_ascii_case("false") {
+        return Tag::Byte(0);
+    }
+
+    let (body, suffix) = match token.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&token[..i], Some(c.to_ascii_lowercase())),
+        _ => (token, None),
+    };
+    let tag = match suffix {
+        Some('b') if is_integer(body) => body.parse().ok().map(Tag::Byte),
+        Some('s') if is_integer(body) => body.parse().ok().map(Tag::Short),
+        Some('l') if is_integer(body) => body.parse().ok().map(Tag::Long),
+        Some('f') if is_decimal(body, false) => body
+            .parse()
+            .ok()
+            .filter(|n: &f32| n.is_finite())
+            .map(Tag::Float),
+        Some('d') if is_decimal(body, false) => body
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map(Tag::Double),
+        None if is_integer(body) => body.parse().ok().map(Tag::Int),
+        None if is_decimal(body, true) => body
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map(Tag::Double),
+        _ => None,
+    };
+
+    tag.unwrap_or_else(|| Tag::String(token.to_owned()))
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_decimal(s: &str, needs_point: bool) -> bool {
+    let s = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    (fraction.is_some() || !needs_point)
+        && !(whole.is_empty() && fraction.is_none_or(str::is_empty))
+        && digits(whole)
+        && fraction.is_none_or(digits)
+        && exponent.is_none_or(is_integer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snbt_round_trips() {
+        for snbt in [
+            r#"{Damage:12,Unbreakable:1b,display:{Name:"{\"text\":\"Sword\"}"}}"#,
+            r#"[1s,2s,3s]"#,
+            r#"[B;1b,-2b]"#,
+            r#"[I;1,2,3]"#,
+            r#"[L;5L]"#,
+            r#"{"a key":1.5f,b:-2.0d,c:9000000000L,d:[]}"#,
+            r#""quotes \" and \\ backslashes""#,
+            r#"[[{}],[[]]]"#,
+        ] {
+            let tag = Tag::parse(snbt).unwrap();
+            assert_eq!(tag.to_string(), snbt);
+            assert_eq!(Tag::parse(&tag.to_string()).unwrap(), tag);
+        }
+    }
+
+    #[test]
+    fn unquoted_values_are_typed_like_minecraft() {
+        assert_eq!(Tag::parse("true").unwrap(), Tag::Byte(1));
+        assert_eq!(Tag::parse("3B").unwrap(), Tag::Byte(3));
+        assert_eq!(Tag::parse("300b").unwrap(), Tag::String("300b".into()));
+        assert_eq!(Tag::parse("1.5").unwrap(), Tag::Double(1.5));
+        assert_eq!(Tag::parse("1e3f").unwrap(), Tag::Float(1000.0));
+        assert_eq!(Tag::parse("1e3").unwrap(), Tag::String("1e3".into()));
+        assert_eq!(
+            Tag::parse("'single'").unwrap(),
+            Tag::String("single".into())
+        );
+        assert_eq!(Tag::parse("[B;300]").unwrap(), Tag::ByteArray(vec![44]));
+    }
+
+    #[test]
+    fn rejects_broken_snbt() {
+        for (snbt, position) in [
+            ("{a:1", 4),
+            ("{a 1}", 3),
+            ("[1,]", 3),
+            ("[I;a]", 3),
+            ("\"open", 0),
+            ("1 2", 2),
+        ] {
+            assert_eq!(
+                Tag::parse(snbt).unwrap_err().position(),
+                position,
+                "{}",
+                snbt
+            );
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Tag::parse(&nested(MAX_DEPTH)).is_ok());
+
+        let err = Tag::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.position(), MAX_DEPTH);
+        assert!(Tag::parse(&"{a:".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let tag = Tag::parse(r#"{display:{Lore:["a","b"]},"a.b":[I;7]}"#).unwrap();
+        let path = |s: &str| s.parse::<TagPath>().unwrap();
+
+        assert_eq!(
+            tag.lookup(&path("display.Lore[1]")).as_deref(),
+            Some(&Tag::String("b".into()))
+        );
+        assert_eq!(
+            tag.lookup(&path(r#""a.b"[0]"#)).as_deref(),
+            Some(&Tag::Int(7))
+        );
+        assert_eq!(tag.lookup(&path("display.Lore[2]")), None);
+        assert_eq!(path(r#""a.b"[0]"#).to_string(), r#""a.b"[0]"#);
+    }
+}
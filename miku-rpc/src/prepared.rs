@@ -0,0 +1,290 @@
+use crate::types::{MoveDirection, RobotSide, RobotSideArg, RotationDirection, Side, SideArg};
+#[cfg(feature = "async")]
+use crate::AsyncDeviceBus;
+use crate::{DeviceBus, EncodeArg, InvokeWriter, ResultValue};
+use miku_codec::{invoke_footer, CodecError};
+use miniserde_miku::Deserialize;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+
+/// A call to one method of one device, with everything but its arguments rendered ahead of time.
+///
+/// These come from the `prepare_` methods generated next to every `#[rpc]` method, and can be kept around and sent as many times as needed. `A` is the method's parameter list as a tuple, and arguments are passed as a tuple that [FitsArgs] it. Trailing `None`s are left out of the call, the same as with the `#[rpc]` method itself.
+///
+/// ```no_run
+/// # use miku_rpc::wrappers::{RedstoneDevice, RedstoneInterface};
+/// # use miku_rpc::DeviceBus;
+/// # fn main() -> std::io::Result<()> {
+/// let mut bus = DeviceBus::new("/dev/hvc0")?;
+/// let redstone: RedstoneDevice = bus.wrap()?.expect("no redstone device!");
+///
+/// let get_input = redstone.prepare_get_redstone_input();
+/// while get_input.call(&mut bus, ("front",))? == 0 {}
+/// # Ok(())
+/// # }
+/// ```
+pub struct PreparedCall<A, R> {
+    header: &'static [u8],
+    footer: Box<[u8]>,
+    void: bool,
+    _signature: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> PreparedCall<A, R> {
+    /// Prepares a call from the header of a method, as rendered by [miku_codec::invoke_header], and the id of the device to call it on.
+    pub fn new(header: &'static [u8], device_id: &str) -> PreparedCall<A, R> {
+        PreparedCall {
+            header,
+            footer: invoke_footer(device_id).into_boxed_slice(),
+            void: false,
+            _signature: PhantomData,
+        }
+    }
+
+    /// Writes out the frame for a call with the given arguments, for sending it some other way.
+    pub fn write<B: FitsArgs<A>>(&self, out: &mut Vec<u8>, args: &B) -> io::Result<()> {
+        let sent = args.sent()?;
+        self.write_sent(out, args, sent);
+        Ok(())
+    }
+
+    fn write_sent<B: FitsArgs<A>>(&self, out: &mut Vec<u8>, args: &B, sent: usize) {
+        let mut call = InvokeWriter::with_header(out, self.header);
+        args.write_args(&mut call, sent);
+        call.finish_with(&self.footer);
+    }
+}
+
+impl<A> PreparedCall<A, ()> {
+    /// Same as [PreparedCall::new], for a method that returns nothing. [PreparedCall::call] accepts a response without any `data` for these, like `#[rpc]` methods without a return type do.
+    pub fn void(header: &'static [u8], device_id: &str) -> PreparedCall<A, ()> {
+        PreparedCall {
+            void: true,
+            ..PreparedCall::new(header, device_id)
+        }
+    }
+}
+
+impl<A, R: Deserialize> PreparedCall<A, R> {
+    /// Sends the call with the given arguments, and gets its result, decoded like [ResultValue::into_data].
+    pub fn call<B: FitsArgs<A>>(&self, bus: &mut DeviceBus, args: B) -> io::Result<R> {
+        self.data(self.call_result(bus, args)?)
+    }
+
+    /// Same as [PreparedCall::call], but keeps track of whether the method returned nothing, `null` or a value.
+    pub fn call_result<B: FitsArgs<A>>(
+        &self,
+        bus: &mut DeviceBus,
+        args: B,
+    ) -> io::Result<ResultValue<R>> {
+        let sent = args.sent()?;
+        bus.call_with_result(|frame| self.write_sent(frame, &args, sent))
+    }
+
+    /// Same as [PreparedCall::call], but over an [AsyncDeviceBus].
+    #[cfg(feature = "async")]
+    pub async fn call_async<B, S>(&self, bus: &mut AsyncDeviceBus<S>, args: B) -> io::Result<R>
+    where
+        B: FitsArgs<A>,
+        S: futures_lite::AsyncRead + futures_lite::AsyncWrite + Unpin,
+    {
+        let sent = args.sent()?;
+        let result = bus
+            .call_with_result(|frame| self.write_sent(frame, &args, sent))
+            .await?;
+        self.data(result)
+    }
+
+    fn data(&self, result: ResultValue<R>) -> io::Result<R> {
+        let data = match result {
+            // a method that returns nothing is prepared as returning (), which would otherwise need a null.
+            ResultValue::Void if self.void => ResultValue::Null.into_data(),
+            result => result.into_data(),
+        };
+
+        Ok(data.ok_or(CodecError::Malformed)?)
+    }
+}
+
+impl<A, R> Clone for PreparedCall<A, R> {
+    fn clone(&self) -> Self {
+        PreparedCall {
+            header: self.header,
+            footer: self.footer.clone(),
+            void: self.void,
+            _signature: PhantomData,
+        }
+    }
+}
+
+impl<A, R> fmt::Debug for PreparedCall<A, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreparedCall")
+            .field("header", &String::from_utf8_lossy(self.header))
+            .field("footer", &String::from_utf8_lossy(&self.footer))
+            .field("void", &self.void)
+            .finish()
+    }
+}
+
+/// Stands for an `impl Trait` parameter in the signature of a [PreparedCall], like `ImplArg<dyn SideArg>`, which takes any argument implementing the trait.
+pub struct ImplArg<T: ?Sized>(PhantomData<T>);
+
+/// An argument that can be passed for a parameter of type `P` of a [PreparedCall].
+///
+/// Reference parameters are written as `&'static` in signatures, and take references with any lifetime.
+pub trait Fits<P>: EncodeArg {
+    /// Whether this is a `None` for an optional parameter, which gets left out of the call instead of being sent as null.
+    fn is_omitted(&self) -> bool {
+        false
+    }
+}
+
+macro_rules! fits_itself {
+    ($($t:ty)*) => {$(
+        impl Fits<$t> for $t {}
+    )*};
+}
+
+fits_itself!(bool i8 i16 i32 i64 isize u8 u16 u32 u64 usize f32 f64 String);
+fits_itself!(Side RobotSide MoveDirection RotationDirection);
+
+impl<T: EncodeArg + ?Sized> Fits<&'static T> for &T {}
+
+impl<A: Fits<P>, P> Fits<Option<P>> for Option<A> {
+    fn is_omitted(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<T: SideArg> Fits<ImplArg<dyn SideArg>> for T {}
+
+impl<T: RobotSideArg> Fits<ImplArg<dyn RobotSideArg>> for T {}
+
+/// A tuple of arguments for a [PreparedCall] whose parameters are the tuple `P`.
+pub trait FitsArgs<P> {
+    /// Returns how many of the arguments get sent, which is all of them but trailing `None`s. A `None` with a `Some` after it is an error, since OC2 tells overloads apart by how many parameters they get.
+    fn sent(&self) -> io::Result<usize>;
+
+    /// Appends the first `sent` arguments to the parameter list.
+    fn write_args(&self, call: &mut InvokeWriter<'_>, sent: usize);
+}
+
+macro_rules! fits_args_tuple {
+    ($(($($arg:ident $param:ident)*))*) => {$(
+        impl<$($arg: Fits<$param>, $param),*> FitsArgs<($($param,)*)> for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn sent(&self) -> io::Result<usize> {
+                let ($($arg,)*) = self;
+                let omitted: &[bool] = &[$($arg.is_omitted()),*];
+                let sent = omitted.iter().rposition(|omitted| !omitted).map_or(0, |i| i + 1);
+                if omitted[..sent].contains(&true) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "an optional argument can't be left out when one after it is given",
+                    ));
+                }
+
+                Ok(sent)
+            }
+
+            #[allow(non_snake_case)]
+            fn write_args(&self, call: &mut InvokeWriter<'_>, sent: usize) {
+                let ($($arg,)*) = self;
+                let args: &[&dyn EncodeArg] = &[$($arg),*];
+                for arg in &args[..sent] {
+                    call.arg(*arg);
+                }
+            }
+        }
+    )*};
+}
+
+fits_args_tuple! {
+    ()
+    (A0 P0)
+    (A0 P0 A1 P1)
+    (A0 P0 A1 P1 A2 P2)
+    (A0 P0 A1 P1 A2 P2 A3 P3)
+    (A0 P0 A1 P1 A2 P2 A3 P3 A4 P4)
+    (A0 P0 A1 P1 A2 P2 A3 P3 A4 P4 A5 P5)
+    (A0 P0 A1 P1 A2 P2 A3 P3 A4 P4 A5 P5 A6 P6)
+    (A0 P0 A1 P1 A2 P2 A3 P3 A4 P4 A5 P5 A6 P6 A7 P7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miku_codec::invoke_header;
+
+    fn prepare<A, R>(method_name: &str) -> PreparedCall<A, R> {
+        PreparedCall::new(
+            Box::leak(invoke_header(method_name).into_boxed_slice()),
+            "d",
+        )
+    }
+
+    fn frame<A, B: FitsArgs<A>>(call: &PreparedCall<A, ()>, args: B) -> io::Result<String> {
+        let mut out = Vec::new();
+        call.write(&mut out, &args)?;
+        Ok(String::from_utf8(out)
+            .unwrap()
+            .trim_matches('\0')
+            .to_owned())
+    }
+
+    #[test]
+    fn trailing_nones_are_left_out() {
+        let call = prepare::<(i32, Option<&'static str>, Option<i32>), ()>("get");
+        assert_eq!(
+            frame(&call, (1, None::<&str>, None::<i32>)).unwrap(),
+            r#"{"type":"invoke","data":{"name":"get","parameters":[1],"deviceId":"d"}}"#
+        );
+        assert_eq!(
+            frame(&call, (1, Some("a"), None::<i32>)).unwrap(),
+            r#"{"type":"invoke","data":{"name":"get","parameters":[1,"a"],"deviceId":"d"}}"#
+        );
+
+        let err = frame(&call, (1, None::<&str>, Some(2))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn references_take_any_lifetime() {
+        let call = prepare::<(&'static str, ImplArg<dyn SideArg>), ()>("get");
+        for i in 0..2 {
+            let name = format!("n{}", i);
+            let side = String::from("up");
+            frame(&call, (name.as_str(), &side)).unwrap();
+            frame(&call, (&*name, Side::Up)).unwrap();
+        }
+    }
+
+    #[test]
+    fn void_and_null_stay_apart() {
+        let mut bus = DeviceBus::serve(|request| {
+            if request.contains(r#""name":"void""#) {
+                r#"{"type":"result"}"#.to_owned()
+            } else {
+                r#"{"type":"result","data":null}"#.to_owned()
+            }
+        })
+        .unwrap();
+
+        let void = PreparedCall::<(), ()>::void(
+            b"\0{\"type\":\"invoke\",\"data\":{\"name\":\"void\",\"parameters\":[",
+            "d",
+        );
+        let null = prepare::<(), ()>("null");
+        let value = prepare::<(), Option<i32>>("void");
+
+        void.call(&mut bus, ()).unwrap();
+        null.call(&mut bus, ()).unwrap();
+        assert_eq!(void.call_result(&mut bus, ()).unwrap(), ResultValue::Void);
+        assert_eq!(null.call_result(&mut bus, ()).unwrap(), ResultValue::Null);
+        // a method that should have returned something doesn't pass for a void one.
+        assert!(prepare::<(), ()>("void").call(&mut bus, ()).is_err());
+        assert_eq!(value.call(&mut bus, ()).unwrap(), None);
+    }
+}
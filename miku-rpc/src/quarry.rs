@@ -0,0 +1,557 @@
+use crate::navigation::{Cell, Navigator};
+use crate::robot::{save_atomically, TrackedRobot};
+use crate::types::{BlockPos, Cuboid, Direction};
+use crate::wrappers::{
+    BlockOperationsInterface, InventoryOperationsInterface, InventoryOperationsModule,
+    RobotInterface,
+};
+use crate::DeviceBus;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// how many times to dig out a block that keeps coming back, like gravel, before giving up on moving into it.
+const MAX_DIG_TRIES: usize = 8;
+
+/// How a [Quarry::run] ended.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum QuarryOutcome {
+    /// Every block in the area was dug, or skipped; see [Quarry::skipped].
+    Finished,
+    /// The robot's inventory filled up, and there was nowhere to unload it, or the chest was full too.
+    InventoryFull,
+    /// The block operations module's tool has no durability left.
+    ToolBroken,
+    /// The robot couldn't get to where it unloads, or back from it.
+    Stuck,
+}
+
+/// A job that digs out a cuboid with a robot and a block operations module.
+///
+/// The area is dug from the top down, in passes three blocks tall: the robot snakes through the middle layer of each pass, digging the blocks above and below it as it goes.
+/// When every slot of the robot's inventory has something in it, it goes to unload everything into an inventory set with [Quarry::unload_with], then comes back. Nothing outside the area gets dug, on the way there or anywhere else.
+///
+/// A quarry made [Quarry::persistent] saves how far it got, and which positions it skipped, after every block, so together with a [TrackedRobot::persistent] it can be run again after the VM restarts and carry on from there.
+///
+/// ```no_run
+/// # use miku_rpc::quarry::{Quarry, QuarryOutcome};
+/// # use miku_rpc::robot::TrackedRobot;
+/// # use miku_rpc::types::{BlockPos, Cuboid, Direction};
+/// # use miku_rpc::wrappers::{BlockOperationsModule, InventoryOperationsModule, Robot};
+/// # use miku_rpc::DeviceBus;
+/// # fn main() -> std::io::Result<()> {
+/// let mut bus = DeviceBus::new("/dev/hvc0")?;
+/// let robot: Robot = bus.wrap()?.expect("not a robot!");
+/// let digger: BlockOperationsModule = bus.wrap()?.expect("no block operations module!");
+/// let inventory: InventoryOperationsModule = bus.wrap()?.expect("no inventory operations module!");
+///
+/// // the robot starts right above a corner of the area, with a chest behind it.
+/// let tracked = TrackedRobot::persistent(&robot, "/home/pose", Default::default())?;
+/// let area = Cuboid::new(BlockPos::new(0, -1, 0), BlockPos::new(15, -30, -15));
+/// let mut quarry = Quarry::new(tracked, &digger, area)
+///     .unload_with(&inventory, BlockPos::ORIGIN, Direction::South)
+///     .persistent("/home/quarry")?;
+///
+/// if quarry.run(&mut bus)? != QuarryOutcome::Finished {
+///     println!("stopped at {}", quarry.robot().pose());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Quarry<'r, R: ?Sized, D: ?Sized, I: ?Sized = InventoryOperationsModule> {
+    navigator: Navigator<'r, R, D>,
+    digger: &'r D,
+    area: Cuboid,
+    inventory: Option<&'r I>,
+    unload: Option<(BlockPos, Direction)>,
+    slots: i32,
+    keep_slots: i32,
+    path: Option<PathBuf>,
+    next: usize,
+    skipped: Vec<BlockPos>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Stop {
+    pos: BlockPos,
+    up: bool,
+    down: bool,
+}
+
+impl<'r, R: RobotInterface + ?Sized, D: BlockOperationsInterface + ?Sized> Quarry<'r, R, D> {
+    pub fn new(robot: TrackedRobot<'r, R>, digger: &'r D, area: Cuboid) -> Quarry<'r, R, D> {
+        Quarry {
+            navigator: Navigator::new(robot).dig_with(digger).dig_within(area),
+            digger,
+            area,
+            inventory: None,
+            unload: None,
+            slots: 24,
+            keep_slots: 0,
+            path: None,
+            next: 0,
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl<'r, R, D, I> Quarry<'r, R, D, I>
+where
+    R: RobotInterface + ?Sized,
+    D: BlockOperationsInterface + ?Sized,
+    I: InventoryOperationsInterface + ?Sized,
+{
+    /// Unloads the robot with `inventory` when it's full, by going to `at` and dropping everything into whatever's in `side` of it.
+    pub fn unload_with<J: InventoryOperationsInterface + ?Sized>(
+        self,
+        inventory: &'r J,
+        at: BlockPos,
+        side: Direction,
+    ) -> Quarry<'r, R, D, J> {
+        Quarry {
+            navigator: self.navigator,
+            digger: self.digger,
+            area: self.area,
+            inventory: Some(inventory),
+            unload: Some((at, side)),
+            slots: self.slots,
+            keep_slots: self.keep_slots,
+            path: self.path,
+            next: self.next,
+            skipped: self.skipped,
+        }
+    }
+
+    /// Sets how many inventory slots the robot has. Defaults to 24.
+    pub fn slots(mut self, slots: i32) -> Self {
+        self.slots = slots;
+        self
+    }
+
+    /// Keeps the first `keep_slots` slots out of the way, like for tools or repair materials: nothing gets dug into them, and they're never unloaded.
+    pub fn keep_slots(mut self, keep_slots: i32) -> Self {
+        self.keep_slots = keep_slots;
+        self
+    }
+
+    /// Saves progress to `path` after every block. If the file already exists, the quarry picks up from the progress saved in it, skipped positions included.
+    pub fn persistent(mut self, path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        match fs::read_to_string(&path) {
+            Ok(saved) => (self.next, self.skipped) = parse_progress(&saved)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    pub fn robot(&self) -> &TrackedRobot<'r, R> {
+        self.navigator.robot()
+    }
+
+    pub fn navigator(&self) -> &Navigator<'r, R, D> {
+        &self.navigator
+    }
+
+    pub fn area(&self) -> Cuboid {
+        self.area
+    }
+
+    /// Returns how many of the robot's stops have been dug so far, and how many there are in total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, stops(self.area).len())
+    }
+
+    /// Returns the positions the robot couldn't get to, and so didn't dig around. For a persistent quarry, this includes the ones from earlier runs.
+    pub fn skipped(&self) -> &[BlockPos] {
+        &self.skipped
+    }
+
+    /// Digs out the area, starting from wherever the last run stopped.
+    pub fn run(&mut self, bus: &mut DeviceBus) -> io::Result<QuarryOutcome> {
+        let stops = stops(self.area);
+        self.robot()
+            .robot()
+            .set_selected_slot(bus, self.keep_slots)?;
+
+        while let Some(&stop) = stops.get(self.next) {
+            if self.digger.durability(bus)? <= 0 {
+                return Ok(QuarryOutcome::ToolBroken);
+            }
+
+            if self.reach(bus, stop.pos)? {
+                if stop.up {
+                    self.clear(bus, Direction::Up)?;
+                }
+                if stop.down {
+                    self.clear(bus, Direction::Down)?;
+                }
+            } else {
+                self.skipped.push(stop.pos);
+            }
+
+            self.next += 1;
+            self.save()?;
+
+            if self.is_full(bus)? {
+                if let Some(outcome) = self.unload(bus)? {
+                    return Ok(outcome);
+                }
+            }
+        }
+
+        Ok(QuarryOutcome::Finished)
+    }
+
+    // gets to `target` by going straight there, or by navigating around whatever's in the way if that doesn't work.
+    fn reach(&mut self, bus: &mut DeviceBus, target: BlockPos) -> io::Result<bool> {
+        if self.tunnel(bus, target)? {
+            return Ok(true);
+        }
+
+        Ok(self.navigator.go_to(bus, target)?.is_arrived())
+    }
+
+    fn tunnel(&mut self, bus: &mut DeviceBus, target: BlockPos) -> io::Result<bool> {
+        loop {
+            let here = self.robot().position();
+            let delta = target - here;
+            let direction = if delta.y != 0 {
+                if delta.y > 0 {
+                    Direction::Up
+                } else {
+                    Direction::Down
+                }
+            } else if delta.x != 0 {
+                if delta.x > 0 {
+                    Direction::East
+                } else {
+                    Direction::West
+                }
+            } else if delta.z != 0 {
+                if delta.z > 0 {
+                    Direction::South
+                } else {
+                    Direction::North
+                }
+            } else {
+                return Ok(true);
+            };
+
+            if !self.dig_step(bus, direction)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    // digs into the next block and moves there, digging again for as long as something falls back in. Nothing outside the area gets dug.
+    fn dig_step(&mut self, bus: &mut DeviceBus, direction: Direction) -> io::Result<bool> {
+        let next = self.robot().position().neighbor(direction);
+        let tries = if self.area.contains(next) {
+            MAX_DIG_TRIES
+        } else {
+            1
+        };
+        for _ in 0..tries {
+            if self.area.contains(next) && self.navigator.map().get(next) != Some(Cell::Free) {
+                self.navigator.dig(bus, direction)?;
+            }
+            if self.navigator.robot_mut().step(bus, direction)? {
+                self.navigator.map_mut().set(next, Cell::Free);
+                return Ok(true);
+            }
+            self.navigator.map_mut().set(next, Cell::Blocked);
+        }
+
+        Ok(false)
+    }
+
+    fn clear(&mut self, bus: &mut DeviceBus, direction: Direction) -> io::Result<()> {
+        let pos = self.robot().position().neighbor(direction);
+        if self.navigator.dig(bus, direction)? {
+            self.navigator.map_mut().set(pos, Cell::Free);
+        }
+        Ok(())
+    }
+
+    // slots fill up in order, so starting from the last one usually finds an empty one right away.
+    fn is_full(&self, bus: &mut DeviceBus) -> io::Result<bool> {
+        let robot = self.robot().robot();
+        for slot in (self.keep_slots..self.slots).rev() {
+            if robot.item_stack_in_slot(bus, slot)?.is_none() {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // goes to unload, and comes back to where it was. Returns why it has to stop, if it does.
+    fn unload(&mut self, bus: &mut DeviceBus) -> io::Result<Option<QuarryOutcome>> {
+        let (Some(inventory), Some((at, side))) = (self.inventory, self.unload) else {
+            return Ok(Some(QuarryOutcome::InventoryFull));
+        };
+
+        let back = self.robot().position();
+        if !self.navigator.go_to(bus, at)?.is_arrived() {
+            return Ok(Some(QuarryOutcome::Stuck));
+        }
+        let Some(side) = self.navigator.robot_mut().side_towards(bus, side)? else {
+            return Ok(Some(QuarryOutcome::Stuck));
+        };
+
+        let robot = self.robot().robot();
+        let mut chest_full = false;
+        for slot in self.keep_slots..self.slots {
+            if let Some(stack) = robot.item_stack_in_slot(bus, slot)? {
+                robot.set_selected_slot(bus, slot)?;
+                let count = stack.count as i32;
+                if inventory.drop(bus, count, side)? < count {
+                    chest_full = true;
+                    break;
+                }
+            }
+        }
+        robot.set_selected_slot(bus, self.keep_slots)?;
+        if chest_full {
+            return Ok(Some(QuarryOutcome::InventoryFull));
+        }
+
+        if !self.navigator.go_to(bus, back)?.is_arrived() {
+            return Ok(Some(QuarryOutcome::Stuck));
+        }
+        Ok(None)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        // the next stop on the first line, then a line for every skipped position.
+        let mut saved = self.next.to_string();
+        for pos in &self.skipped {
+            saved += &format!("\n{} {} {}", pos.x, pos.y, pos.z);
+        }
+        save_atomically(path, &saved)
+    }
+}
+
+fn parse_progress(saved: &str) -> io::Result<(usize, Vec<BlockPos>)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid saved quarry progress {:?}", saved),
+        )
+    };
+
+    let mut lines = saved.lines();
+    let next = lines
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let skipped = lines
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let coords: Vec<i32> = line
+                .split_whitespace()
+                .map(|n| n.parse().map_err(|_| invalid()))
+                .collect::<io::Result<_>>()?;
+            match coords[..] {
+                [x, y, z] => Ok(BlockPos::new(x, y, z)),
+                _ => Err(invalid()),
+            }
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok((next, skipped))
+}
+
+// the middle of each pass, snaking back and forth so every stop is next to the one before it, with each pass starting right below where the last one ended.
+fn stops(area: Cuboid) -> Vec<Stop> {
+    let Cuboid { min, max } = area;
+    let span = |from: i32, to: i32, forward: bool| -> Vec<i32> {
+        if forward {
+            (from..=to).collect()
+        } else {
+            (from..=to).rev().collect()
+        }
+    };
+
+    let mut stops = Vec::new();
+    let (mut x_forward, mut z_forward) = (true, true);
+    let mut top = max.y;
+    while top >= min.y {
+        let y = (top - 1).max(min.y);
+        for z in span(min.z, max.z, z_forward) {
+            for x in span(min.x, max.x, x_forward) {
+                stops.push(Stop {
+                    pos: BlockPos::new(x, y, z),
+                    up: y < top,
+                    down: y > min.y,
+                });
+            }
+            x_forward = !x_forward;
+        }
+
+        z_forward = !z_forward;
+        top = y - 2;
+    }
+
+    stops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::{BlockOperationsModule, RPCDevice, Robot};
+    use std::sync::{Arc, LazyLock, Mutex};
+
+    static ROBOT: LazyLock<Robot> = LazyLock::new(|| Robot::from_id(String::new()));
+    static DIGGER: LazyLock<BlockOperationsModule> =
+        LazyLock::new(|| BlockOperationsModule::from_id(String::new()));
+    static INVENTORY: LazyLock<InventoryOperationsModule> =
+        LazyLock::new(|| InventoryOperationsModule::from_id(String::new()));
+
+    fn quarry(area: Cuboid) -> Quarry<'static, Robot, BlockOperationsModule> {
+        Quarry::new(
+            TrackedRobot::new(&*ROBOT, Default::default()),
+            &*DIGGER,
+            area,
+        )
+    }
+
+    #[test]
+    fn stops_snake_through_the_area() {
+        let area = Cuboid::new(BlockPos::new(0, -1, 0), BlockPos::new(2, -7, -3));
+        let stops = stops(area);
+
+        // every stop is one move away from the one before it, starting from the top corner, and each pass starts right below where the last one ended, even when the last pass is thinner.
+        assert_eq!(stops[0].pos, BlockPos::new(0, -2, -3));
+        for pair in stops.windows(2) {
+            let step = pair[1].pos - pair[0].pos;
+            let down = step.x == 0 && step.z == 0 && (-3..0).contains(&step.y);
+            assert!(
+                step.manhattan_distance(BlockPos::ORIGIN) == 1 || down,
+                "{} to {}",
+                pair[0].pos,
+                pair[1].pos
+            );
+        }
+        assert_eq!(stops.len(), 3 * 4 * 3);
+
+        // and between them they dig every block in the area exactly once, without going outside of it.
+        let mut dug: Vec<BlockPos> = stops
+            .iter()
+            .flat_map(|stop| {
+                let up = stop.up.then(|| stop.pos.neighbor(Direction::Up));
+                let down = stop.down.then(|| stop.pos.neighbor(Direction::Down));
+                [Some(stop.pos), up, down].into_iter().flatten()
+            })
+            .collect();
+        assert!(dug.iter().all(|&pos| area.contains(pos)));
+        dug.sort_by_key(|pos| (pos.y, pos.z, pos.x));
+        assert_eq!(dug, area.positions().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn thin_areas_still_get_a_pass() {
+        let layer = stops(Cuboid::new(BlockPos::new(0, 5, 0), BlockPos::new(1, 5, 1)));
+        assert_eq!(layer.len(), 4);
+        assert!(layer.iter().all(|stop| !stop.up && !stop.down));
+
+        // two layers are dug from the lower one, looking up.
+        let two = stops(Cuboid::new(BlockPos::new(0, 5, 0), BlockPos::new(0, 6, 0)));
+        assert_eq!(two.len(), 1);
+        assert_eq!(two[0].pos, BlockPos::new(0, 5, 0));
+        assert!(two[0].up && !two[0].down);
+    }
+
+    #[test]
+    fn progress_and_skipped_positions_are_saved() {
+        let path = std::env::temp_dir().join(format!("miku-quarry-{}", std::process::id()));
+        let area = Cuboid::new(BlockPos::new(0, -1, 0), BlockPos::new(3, -3, -3));
+
+        fs::write(&path, "5").unwrap();
+        let mut saved = quarry(area).persistent(&path).unwrap();
+        assert_eq!(saved.progress().0, 5);
+        assert!(saved.skipped().is_empty());
+
+        saved.next = 9;
+        saved.skipped = vec![BlockPos::new(1, -2, -3), BlockPos::new(-4, 5, 6)];
+        saved.save().unwrap();
+        let loaded = quarry(area).persistent(&path).unwrap();
+        assert_eq!(loaded.progress().0, 9);
+        assert_eq!(loaded.skipped(), saved.skipped());
+
+        fs::write(&path, "5\n1 2").unwrap();
+        let err = quarry(area).persistent(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    // a robot whose first `filled` slots have items in them, next to a chest that takes `room` items.
+    fn inventory_bus(filled: i32, room: i32) -> (DeviceBus, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let log = calls.clone();
+        let bus = DeviceBus::serve(move |request| {
+            let name = request.split(r#""name":""#).nth(1).unwrap();
+            let name = &name[..name.find('"').unwrap()];
+            let parameters = request.split(r#""parameters":["#).nth(1).unwrap();
+            let parameters = &parameters[..parameters.find(']').unwrap()];
+            log.lock().unwrap().push(format!("{} {}", name, parameters));
+
+            let data = match name {
+                "getStackInSlot" if parameters.parse::<i32>().unwrap() < filled => {
+                    r#"{"id":"minecraft:cobblestone","Count":64}"#.to_owned()
+                }
+                "getStackInSlot" => "null".to_owned(),
+                "drop" => room.min(64).to_string(),
+                _ => "null".to_owned(),
+            };
+            format!(r#"{{"type":"result","data":{}}}"#, data)
+        })
+        .unwrap();
+
+        (bus, calls)
+    }
+
+    #[test]
+    fn full_means_every_slot() {
+        let quarry = quarry(Cuboid::new(BlockPos::ORIGIN, BlockPos::ORIGIN))
+            .slots(4)
+            .keep_slots(1);
+
+        let (mut bus, calls) = inventory_bus(3, 0);
+        assert!(!quarry.is_full(&mut bus).unwrap());
+        assert_eq!(*calls.lock().unwrap(), ["getStackInSlot 3"]);
+
+        let (mut bus, calls) = inventory_bus(4, 0);
+        assert!(quarry.is_full(&mut bus).unwrap());
+        assert_eq!(calls.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn a_full_chest_stops_the_quarry() {
+        let area = Cuboid::new(BlockPos::new(0, -1, 0), BlockPos::new(3, -3, -3));
+        let mut quarry =
+            quarry(area)
+                .slots(4)
+                .unload_with(&*INVENTORY, BlockPos::ORIGIN, Direction::Up);
+
+        let (mut bus, calls) = inventory_bus(4, 0);
+        assert_eq!(
+            quarry.unload(&mut bus).unwrap(),
+            Some(QuarryOutcome::InventoryFull)
+        );
+        // it gives up on the first stack that doesn't fit, instead of going back to dig.
+        let drops = calls.lock().unwrap();
+        assert_eq!(
+            drops.iter().filter(|call| call.starts_with("drop")).count(),
+            1
+        );
+        assert_eq!(drops.last().unwrap(), "setSelectedSlot 0");
+    }
+}
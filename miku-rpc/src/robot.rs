@@ -0,0 +1,1085 @@
+use crate::types::{
+    BlockPos, Direction, Facing, MoveDirection, RobotActionResult, RobotSide, RotationDirection,
+};
+use crate::wrappers::RobotInterface;
+use crate::DeviceBus;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a game tick lasts. Robots work through their action queue once per tick.
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// How to wait for robot actions: how often to poll, and for how long.
+///
+/// The default polls every 100ms and never gives up, which is how [crate::wrappers::RobotInterface::wait_for_action] waits.
+///
+/// ```
+/// # use miku_rpc::robot::WaitPolicy;
+/// # use std::time::Duration;
+/// let policy = WaitPolicy::new()
+///     .timeout(Duration::from_secs(5))
+///     .backoff(Duration::from_millis(50), Duration::from_millis(400), 2.0)
+///     .tick_aligned(true);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaitPolicy {
+    timeout: Option<Duration>,
+    interval: Duration,
+    max_interval: Duration,
+    backoff: f32,
+    tick_aligned: bool,
+}
+
+impl Default for WaitPolicy {
+    fn default() -> Self {
+        WaitPolicy {
+            timeout: None,
+            interval: Duration::from_millis(100),
+            max_interval: Duration::from_millis(100),
+            backoff: 1.0,
+            tick_aligned: false,
+        }
+    }
+}
+
+impl WaitPolicy {
+    pub fn new() -> WaitPolicy {
+        WaitPolicy::default()
+    }
+
+    /// Gives up once `timeout` has passed since the wait started.
+    pub fn timeout(mut self, timeout: Duration) -> WaitPolicy {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Polls at a fixed interval.
+    pub fn interval(mut self, interval: Duration) -> WaitPolicy {
+        self.interval = interval;
+        self.max_interval = interval;
+        self.backoff = 1.0;
+        self
+    }
+
+    /// Starts polling every `initial`, then multiplies the interval by `factor` after every poll, up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration, factor: f32) -> WaitPolicy {
+        self.interval = initial;
+        self.max_interval = max.max(initial);
+        self.backoff = factor.max(1.0);
+        self
+    }
+
+    /// Rounds every interval up to a whole number of [TICK]s, counted from when the wait started, so polls don't land in the middle of a tick that can't have changed anything yet.
+    pub fn tick_aligned(mut self, tick_aligned: bool) -> WaitPolicy {
+        self.tick_aligned = tick_aligned;
+        self
+    }
+
+    /// Starts a wait under this policy.
+    pub fn start(&self) -> Waiter<'_> {
+        Waiter {
+            policy: self,
+            started: Instant::now(),
+            interval: self.interval,
+        }
+    }
+}
+
+/// A wait in progress, started with [WaitPolicy::start].
+#[derive(Debug)]
+pub struct Waiter<'a> {
+    policy: &'a WaitPolicy,
+    started: Instant,
+    interval: Duration,
+}
+
+impl Waiter<'_> {
+    /// Sleeps until it's time to poll again. Returns false, without sleeping, if the policy's timeout would be up by then.
+    pub fn sleep(&mut self) -> bool {
+        match self.next_delay() {
+            Some(delay) => {
+                thread::sleep(delay);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns how long to wait before polling again, or None if the timeout would be up by then.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.delay_after(self.started.elapsed())
+    }
+
+    // next_delay(), for a wait that has been going on for `elapsed`.
+    fn delay_after(&mut self, elapsed: Duration) -> Option<Duration> {
+        let mut delay = self.interval;
+        if self.policy.tick_aligned {
+            let target = elapsed + delay;
+            let ticks = target.as_nanos().div_ceil(TICK.as_nanos());
+            delay = TICK * ticks as u32 - elapsed;
+        }
+
+        if self
+            .policy
+            .timeout
+            .is_some_and(|timeout| elapsed + delay > timeout)
+        {
+            return None;
+        }
+
+        // in f32, even a factor of 1 turns 40ms into 39.999999ms.
+        self.interval = self
+            .interval
+            .mul_f64(f64::from(self.policy.backoff))
+            .min(self.policy.max_interval);
+        Some(delay)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// How a robot action ended up, as reported by [crate::wrappers::RobotInterface::wait_for_action_with].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ActionOutcome {
+    /// The action completed.
+    Success,
+    /// The robot couldn't complete the action, like when a block was in the way.
+    Failed,
+    /// The robot's action queue stayed full until the wait timed out, so the action was never enqueued.
+    NotEnqueued,
+    /// The action was still running when the wait timed out. It may still complete later.
+    TimedOut { action: i32 },
+    /// The action's result was dropped before it could be read, since robots only keep a limited number of them. Whether it succeeded is unknown.
+    Expired { action: i32 },
+    /// The id doesn't belong to any action the robot has enqueued.
+    UnknownAction { action: i32 },
+    /// The action was never enqueued, because one before it failed. See [ActionScheduler::stop_on_failure].
+    Skipped,
+}
+
+impl ActionOutcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, ActionOutcome::Success)
+    }
+
+    /// Returns the id of the action, for outcomes that have one.
+    pub fn action(&self) -> Option<i32> {
+        match *self {
+            ActionOutcome::TimedOut { action }
+            | ActionOutcome::Expired { action }
+            | ActionOutcome::UnknownAction { action } => Some(action),
+            _ => None,
+        }
+    }
+
+    /// Turns this into whether the action succeeded, with an error for the outcomes that can't say.
+    pub fn succeeded(self) -> io::Result<bool> {
+        match self {
+            ActionOutcome::Success => Ok(true),
+            ActionOutcome::Failed | ActionOutcome::Skipped => Ok(false),
+            ActionOutcome::NotEnqueued | ActionOutcome::TimedOut { .. } => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, self))
+            }
+            ActionOutcome::Expired { .. } | ActionOutcome::UnknownAction { .. } => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, self))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ActionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionOutcome::Success => f.write_str("action succeeded"),
+            ActionOutcome::Failed => f.write_str("action failed"),
+            ActionOutcome::NotEnqueued => f.write_str("timed out waiting to enqueue action"),
+            ActionOutcome::TimedOut { action } => {
+                write!(f, "timed out waiting for action {}", action)
+            }
+            ActionOutcome::Expired { action } => {
+                write!(f, "the result of action {} has expired", action)
+            }
+            ActionOutcome::UnknownAction { action } => write!(f, "unknown action {}", action),
+            ActionOutcome::Skipped => f.write_str("action skipped after an earlier one failed"),
+        }
+    }
+}
+
+impl Error for ActionOutcome {}
+
+/// A single thing for a robot to do.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RobotAction {
+    Move(MoveDirection),
+    Turn(RotationDirection),
+}
+
+impl RobotAction {
+    fn enqueue<R: RobotInterface + ?Sized>(
+        self,
+        robot: &R,
+        bus: &mut DeviceBus,
+    ) -> io::Result<bool> {
+        match self {
+            RobotAction::Move(direction) => robot.move_async(bus, direction),
+            RobotAction::Turn(direction) => robot.turn_async(bus, direction),
+        }
+    }
+}
+
+impl From<MoveDirection> for RobotAction {
+    fn from(direction: MoveDirection) -> RobotAction {
+        RobotAction::Move(direction)
+    }
+}
+
+impl From<RotationDirection> for RobotAction {
+    fn from(direction: RotationDirection) -> RobotAction {
+        RobotAction::Turn(direction)
+    }
+}
+
+/// What happened to one action run by an [ActionScheduler].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ActionReport {
+    pub action: RobotAction,
+    /// The id the robot gave the action, if it was enqueued.
+    pub id: Option<i32>,
+    pub outcome: ActionOutcome,
+}
+
+/// Runs a sequence of actions on a robot, keeping its action queue full instead of waiting for each action before enqueueing the next, so they run back to back at the robot's own speed.
+///
+/// Actions are enqueued until the robot's queue is full, then retried as earlier ones finish. The [WaitPolicy] decides how often the robot gets polled, and its timeout counts from the last time an action was enqueued or finished.
+///
+/// ```no_run
+/// # use miku_rpc::robot::ActionScheduler;
+/// # use miku_rpc::types::{MoveDirection, RotationDirection};
+/// # use miku_rpc::wrappers::Robot;
+/// # use miku_rpc::DeviceBus;
+/// # fn main() -> std::io::Result<()> {
+/// let mut bus = DeviceBus::new("/dev/hvc0")?;
+/// let robot: Robot = bus.wrap()?.expect("not a robot!");
+///
+/// let mut scheduler = ActionScheduler::new(&robot);
+/// scheduler.extend([MoveDirection::Forward; 8]);
+/// scheduler.push(RotationDirection::Left);
+/// scheduler.extend([MoveDirection::Forward; 8]);
+///
+/// for report in scheduler.run(&mut bus)? {
+///     println!("{:?}: {}", report.action, report.outcome);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ActionScheduler<'r, R: ?Sized> {
+    robot: &'r R,
+    policy: WaitPolicy,
+    stop_on_failure: bool,
+    pending: VecDeque<RobotAction>,
+    in_flight: VecDeque<(RobotAction, i32)>,
+}
+
+impl<'r, R: RobotInterface + ?Sized> ActionScheduler<'r, R> {
+    pub fn new(robot: &'r R) -> ActionScheduler<'r, R> {
+        ActionScheduler {
+            robot,
+            policy: WaitPolicy::default(),
+            stop_on_failure: false,
+            pending: VecDeque::new(),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Sets how to wait for the robot.
+    pub fn policy(mut self, policy: WaitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Stops enqueueing actions once one fails, and reports the rest as [ActionOutcome::Skipped]. Actions that were already enqueued still run, since there's no taking them back.
+    pub fn stop_on_failure(mut self, stop_on_failure: bool) -> Self {
+        self.stop_on_failure = stop_on_failure;
+        self
+    }
+
+    pub fn push(&mut self, action: impl Into<RobotAction>) {
+        self.pending.push_back(action.into());
+    }
+
+    pub fn extend<A: Into<RobotAction>>(&mut self, actions: impl IntoIterator<Item = A>) {
+        self.pending.extend(actions.into_iter().map(Into::into));
+    }
+
+    /// Whether every action has been run.
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_empty()
+    }
+
+    /// Runs every action pushed so far, and returns what happened to each of them, in order.
+    pub fn run(&mut self, bus: &mut DeviceBus) -> io::Result<Vec<ActionReport>> {
+        let mut reports = Vec::with_capacity(self.pending.len() + self.in_flight.len());
+        // the waiter borrows the policy, and self gets borrowed mutably while it's alive.
+        let policy = self.policy.clone();
+        let mut waiter = policy.start();
+        let mut skipped = Vec::new();
+
+        while !self.is_done() {
+            let mut progressed = self.fill(bus)?;
+            while let Some(report) = self.poll_oldest(bus)? {
+                progressed = true;
+                let failed = !report.outcome.is_success();
+                reports.push(report);
+
+                if failed && self.stop_on_failure {
+                    skipped.extend(self.pending.drain(..));
+                }
+            }
+
+            if self.is_done() {
+                break;
+            }
+            if progressed {
+                waiter = policy.start();
+            }
+            if !waiter.sleep() {
+                reports.extend(self.in_flight.drain(..).map(|(action, id)| ActionReport {
+                    action,
+                    id: Some(id),
+                    outcome: ActionOutcome::TimedOut { action: id },
+                }));
+                reports.extend(self.pending.drain(..).map(|action| ActionReport {
+                    action,
+                    id: None,
+                    outcome: ActionOutcome::NotEnqueued,
+                }));
+            }
+        }
+
+        // actions that were already enqueued still report in order before the skipped ones.
+        reports.extend(skipped.into_iter().map(|action| ActionReport {
+            action,
+            id: None,
+            outcome: ActionOutcome::Skipped,
+        }));
+        Ok(reports)
+    }
+
+    // enqueues pending actions until the robot's queue is full; returns whether any were.
+    fn fill(&mut self, bus: &mut DeviceBus) -> io::Result<bool> {
+        let mut enqueued = false;
+        while let Some(&action) = self.pending.front() {
+            if !action.enqueue(self.robot, bus)? {
+                break;
+            }
+
+            let id = self.robot.get_last_action_id(bus)?;
+            self.pending.pop_front();
+            self.in_flight.push_back((action, id));
+            enqueued = true;
+        }
+
+        Ok(enqueued)
+    }
+
+    // the robot works through its queue in order, so only the oldest action can have finished next.
+    fn poll_oldest(&mut self, bus: &mut DeviceBus) -> io::Result<Option<ActionReport>> {
+        let Some(&(action, id)) = self.in_flight.front() else {
+            return Ok(None);
+        };
+
+        let outcome = match self.robot.try_get_action_result(bus, id)? {
+            Some(RobotActionResult::Incomplete) => return Ok(None),
+            Some(RobotActionResult::Success) => ActionOutcome::Success,
+            Some(RobotActionResult::Failure) => ActionOutcome::Failed,
+            Some(RobotActionResult::Unknown(result)) => {
+                return Err(RobotActionResult::unknown(&result))
+            }
+            None if id <= self.robot.get_last_action_id(bus)? => {
+                ActionOutcome::Expired { action: id }
+            }
+            None => ActionOutcome::UnknownAction { action: id },
+        };
+
+        self.in_flight.pop_front();
+        Ok(Some(ActionReport {
+            action,
+            id: Some(id),
+            outcome,
+        }))
+    }
+}
+
+/// Where a robot is, and which way it's facing.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Pose {
+    pub pos: BlockPos,
+    pub facing: Facing,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose::new(BlockPos::ORIGIN, Facing::North)
+    }
+}
+
+impl Pose {
+    pub const fn new(pos: BlockPos, facing: Facing) -> Pose {
+        Pose { pos, facing }
+    }
+
+    /// Returns the pose after `action` succeeds.
+    pub fn apply(self, action: RobotAction) -> Pose {
+        match action {
+            RobotAction::Move(direction) => Pose {
+                pos: self.pos.neighbor(direction.to_absolute(self.facing)),
+                ..self
+            },
+            RobotAction::Turn(rotation) => Pose {
+                facing: self.facing.rotate(rotation),
+                ..self
+            },
+        }
+    }
+
+    /// Takes this pose as relative to `origin`, where relative north is `origin`'s facing, and returns it in the same frame as `origin`.
+    pub fn relative_to(self, origin: Pose) -> Pose {
+        let mut offset = self.pos;
+        let mut facing = self.facing;
+        let mut frame = Facing::North;
+        while frame != origin.facing {
+            offset = BlockPos::new(-offset.z, offset.y, offset.x);
+            facing = facing.right();
+            frame = frame.right();
+        }
+
+        Pose::new(origin.pos + offset, facing)
+    }
+}
+
+impl fmt::Display for Pose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.pos.x, self.pos.y, self.pos.z, self.facing
+        )
+    }
+}
+
+/// Wraps a robot to keep track of its [Pose] by dead reckoning, updating it after every action that succeeds.
+///
+/// Poses start out relative to wherever the robot was when tracking started, facing north, unless told otherwise with [TrackedRobot::set_pose] or [TrackedRobot::set_origin].
+/// A tracker made with [TrackedRobot::persistent] saves the pose to a file after every change, so it can pick up where it left off after the VM restarts.
+///
+/// Only actions done through the tracker are tracked. Actions that time out or whose results expire may or may not have happened, and leave the pose unchanged.
+///
+/// ```no_run
+/// # use miku_rpc::robot::TrackedRobot;
+/// # use miku_rpc::types::BlockPos;
+/// # use miku_rpc::wrappers::Robot;
+/// # use miku_rpc::DeviceBus;
+/// # fn main() -> std::io::Result<()> {
+/// let mut bus = DeviceBus::new("/dev/hvc0")?;
+/// let robot: Robot = bus.wrap()?.expect("not a robot!");
+///
+/// let mut tracked = TrackedRobot::persistent(&robot, "/home/pose", Default::default())?;
+/// if !tracked.go_to(&mut bus, BlockPos::ORIGIN)? {
+///     println!("got stuck at {}", tracked.pose());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TrackedRobot<'r, R: ?Sized> {
+    robot: &'r R,
+    pose: Pose,
+    path: Option<PathBuf>,
+    policy: WaitPolicy,
+}
+
+impl<'r, R: RobotInterface + ?Sized> TrackedRobot<'r, R> {
+    /// Starts tracking a robot that's currently at `pose`.
+    pub fn new(robot: &'r R, pose: Pose) -> TrackedRobot<'r, R> {
+        TrackedRobot {
+            robot,
+            pose,
+            path: None,
+            policy: WaitPolicy::default(),
+        }
+    }
+
+    /// Starts tracking a robot, saving its pose to `path`. If the file already exists, the pose is loaded from it, otherwise the robot is taken to be at `pose`.
+    pub fn persistent(
+        robot: &'r R,
+        path: impl Into<PathBuf>,
+        pose: Pose,
+    ) -> io::Result<TrackedRobot<'r, R>> {
+        let path = path.into();
+        let pose = match fs::read_to_string(&path) {
+            Ok(saved) => parse_pose(&saved)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => pose,
+            Err(e) => return Err(e),
+        };
+
+        let mut tracked = TrackedRobot::new(robot, pose);
+        tracked.path = Some(path);
+        tracked.save()?;
+        Ok(tracked)
+    }
+
+    /// Sets how to wait for the robot's actions.
+    pub fn policy(mut self, policy: WaitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn robot(&self) -> &'r R {
+        self.robot
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    pub fn position(&self) -> BlockPos {
+        self.pose.pos
+    }
+
+    pub fn facing(&self) -> Facing {
+        self.pose.facing
+    }
+
+    /// Overrides the tracked pose, like when the robot's actual position becomes known.
+    pub fn set_pose(&mut self, pose: Pose) -> io::Result<()> {
+        self.pose = pose;
+        self.save()
+    }
+
+    /// Declares that the pose tracking started from was actually `origin`, and moves the tracked pose over to the same frame as `origin`.
+    pub fn set_origin(&mut self, origin: Pose) -> io::Result<()> {
+        self.set_pose(self.pose.relative_to(origin))
+    }
+
+    /// Updates the pose with a report from an [ActionScheduler] running on the same robot.
+    pub fn record(&mut self, report: &ActionReport) -> io::Result<()> {
+        if !report.outcome.is_success() {
+            return Ok(());
+        }
+
+        self.set_pose(self.pose.apply(report.action))
+    }
+
+    /// Same as [RobotInterface::move_wait], but tracks the move.
+    pub fn move_wait(&mut self, bus: &mut DeviceBus, direction: MoveDirection) -> io::Result<bool> {
+        self.act(bus, RobotAction::Move(direction))
+    }
+
+    /// Same as [RobotInterface::turn_wait], but tracks the turn.
+    pub fn turn_wait(
+        &mut self,
+        bus: &mut DeviceBus,
+        direction: RotationDirection,
+    ) -> io::Result<bool> {
+        self.act(bus, RobotAction::Turn(direction))
+    }
+
+    /// Moves one block in an absolute direction, without turning.
+    pub fn step(&mut self, bus: &mut DeviceBus, direction: Direction) -> io::Result<bool> {
+        self.move_wait(bus, direction.to_relative(self.pose.facing))
+    }
+
+    /// Turns until the robot faces `facing`.
+    pub fn face(&mut self, bus: &mut DeviceBus, facing: Facing) -> io::Result<bool> {
+        for &rotation in self.pose.facing.turns_to(facing) {
+            if !self.turn_wait(bus, rotation)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Turns so that `direction` is one of the robot's sides, and returns which. Returns None if a turn fails.
+    pub fn side_towards(
+        &mut self,
+        bus: &mut DeviceBus,
+        direction: Direction,
+    ) -> io::Result<Option<RobotSide>> {
+        Ok(match direction.facing() {
+            None if direction == Direction::Up => Some(RobotSide::Up),
+            None => Some(RobotSide::Down),
+            Some(facing) => self.face(bus, facing)?.then_some(RobotSide::Front),
+        })
+    }
+
+    /// Moves to `target` one axis at a time, without turning. Going up happens first and going down last, so the robot clears whatever is in the way when it can.
+    /// Returns false, leaving the robot wherever it got stuck, if a move fails.
+    pub fn go_to(&mut self, bus: &mut DeviceBus, target: BlockPos) -> io::Result<bool> {
+        let rising = target.y > self.pose.pos.y;
+        if rising
+            && !self.go_along(
+                bus,
+                Direction::Up,
+                Direction::Down,
+                target.y - self.pose.pos.y,
+            )?
+        {
+            return Ok(false);
+        }
+        if !self.go_along(
+            bus,
+            Direction::East,
+            Direction::West,
+            target.x - self.pose.pos.x,
+        )? {
+            return Ok(false);
+        }
+        if !self.go_along(
+            bus,
+            Direction::South,
+            Direction::North,
+            target.z - self.pose.pos.z,
+        )? {
+            return Ok(false);
+        }
+        if !rising
+            && !self.go_along(
+                bus,
+                Direction::Up,
+                Direction::Down,
+                target.y - self.pose.pos.y,
+            )?
+        {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn go_along(
+        &mut self,
+        bus: &mut DeviceBus,
+        positive: Direction,
+        negative: Direction,
+        delta: i32,
+    ) -> io::Result<bool> {
+        let direction = if delta < 0 { negative } else { positive };
+        for _ in 0..delta.unsigned_abs() {
+            if !self.step(bus, direction)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn act(&mut self, bus: &mut DeviceBus, action: RobotAction) -> io::Result<bool> {
+        let outcome = match action {
+            RobotAction::Move(direction) => self.robot.move_with(bus, direction, &self.policy)?,
+            RobotAction::Turn(rotation) => self.robot.turn_with(bus, rotation, &self.policy)?,
+        };
+
+        self.record(&ActionReport {
+            action,
+            id: outcome.action(),
+            outcome,
+        })?;
+        outcome.succeeded()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        save_atomically(path, &self.pose.to_string())
+    }
+}
+
+/// Writes `contents` next to `path` and renames it over it, so a restart mid-write can't leave half a file behind.
+pub(crate) fn save_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    // appended rather than swapped for the extension, so "quarry.pose" and "quarry.progress" don't share a temporary file.
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(temp, path)
+}
+
+fn parse_pose(saved: &str) -> io::Result<Pose> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid saved pose {:?}", saved),
+        )
+    };
+
+    let mut parts = saved.split_whitespace();
+    let mut coord = || {
+        parts
+            .next()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(invalid)
+    };
+    let pos = BlockPos::new(coord()?, coord()?, coord()?);
+    let facing = parts
+        .next()
+        .and_then(|f| f.parse().ok())
+        .ok_or_else(invalid)?;
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(Pose::new(pos, facing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::{RPCDevice, Robot};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    // a robot that finishes one queued action every time a result is asked for, and fails the actions in `failing`.
+    #[derive(Default)]
+    struct FakeRobot {
+        capacity: usize,
+        failing: Vec<i32>,
+        stuck: bool,
+        queue: VecDeque<i32>,
+        results: HashMap<i32, &'static str>,
+        last_id: i32,
+        log: Vec<String>,
+    }
+
+    impl FakeRobot {
+        fn respond(&mut self, request: &str) -> String {
+            let name = request.split(r#""name":""#).nth(1).unwrap();
+            let name = &name[..name.find('"').unwrap()];
+            let parameters = request.split(r#""parameters":["#).nth(1).unwrap();
+            let parameters = &parameters[..parameters.find(']').unwrap()];
+
+            let data = match name {
+                "move" | "turn" if self.queue.len() < self.capacity => {
+                    self.last_id += 1;
+                    self.queue.push_back(self.last_id);
+                    self.log.push(format!("{} {}", name, self.last_id));
+                    "true".to_owned()
+                }
+                "move" | "turn" => "false".to_owned(),
+                "getLastActionId" => self.last_id.to_string(),
+                "getActionResult" => {
+                    let id = parameters.parse().unwrap();
+                    if !self.stuck {
+                        if let Some(done) = self.queue.pop_front() {
+                            let failed = self.failing.contains(&done);
+                            self.results
+                                .insert(done, if failed { "FAILURE" } else { "SUCCESS" });
+                        }
+                    }
+                    self.log.push(format!("result {}", id));
+                    match self.results.get(&id) {
+                        Some(result) => format!(r#""{}""#, result),
+                        None if self.queue.contains(&id) => r#""INCOMPLETE""#.to_owned(),
+                        None => "null".to_owned(),
+                    }
+                }
+                _ => panic!("unexpected call {}", request),
+            };
+
+            format!(r#"{{"type":"result","data":{}}}"#, data)
+        }
+    }
+
+    fn fake_bus(robot: FakeRobot) -> (DeviceBus, Arc<Mutex<FakeRobot>>) {
+        let robot = Arc::new(Mutex::new(robot));
+        let server = robot.clone();
+        let bus = DeviceBus::serve(move |request| server.lock().unwrap().respond(request)).unwrap();
+        (bus, robot)
+    }
+
+    fn quick() -> WaitPolicy {
+        WaitPolicy::new().interval(Duration::from_millis(1))
+    }
+
+    fn outcomes(reports: &[ActionReport]) -> Vec<(Option<i32>, ActionOutcome)> {
+        reports
+            .iter()
+            .map(|report| (report.id, report.outcome))
+            .collect()
+    }
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    // the delays a wait under `policy` asks for, on a clock that only moves while it sleeps and for `poll` every time it polls.
+    fn delays(policy: &WaitPolicy, poll: Duration, polls: usize) -> Vec<Option<Duration>> {
+        let mut waiter = policy.start();
+        let mut elapsed = Duration::ZERO;
+        (0..polls)
+            .map(|_| {
+                elapsed += poll;
+                let delay = waiter.delay_after(elapsed);
+                elapsed += delay.unwrap_or_default();
+                delay
+            })
+            .collect()
+    }
+
+    #[test]
+    fn waits_back_off_up_to_the_cap() {
+        let policy =
+            WaitPolicy::new().backoff(Duration::from_millis(10), Duration::from_millis(100), 2.0);
+        assert_eq!(
+            delays(&policy, Duration::ZERO, 6),
+            [ms(10), ms(20), ms(40), ms(80), ms(100), ms(100)]
+        );
+
+        // a fixed interval never grows.
+        let policy = WaitPolicy::new().interval(Duration::from_millis(30));
+        assert_eq!(delays(&policy, Duration::from_millis(7), 3), [ms(30); 3]);
+    }
+
+    #[test]
+    fn tick_aligned_waits_end_on_ticks() {
+        let policy = WaitPolicy::new()
+            .interval(Duration::from_millis(30))
+            .tick_aligned(true);
+        // polls land 5ms after each tick, so the next tick is always 45ms away.
+        assert_eq!(delays(&policy, Duration::from_millis(5), 3), [ms(45); 3]);
+
+        let policy = WaitPolicy::new()
+            .backoff(Duration::from_millis(20), Duration::from_millis(200), 2.0)
+            .tick_aligned(true);
+        assert_eq!(
+            delays(&policy, Duration::ZERO, 5),
+            [ms(50), ms(50), ms(100), ms(200), ms(200)]
+        );
+    }
+
+    #[test]
+    fn waits_stop_before_the_timeout() {
+        let policy = WaitPolicy::new()
+            .interval(Duration::from_millis(40))
+            .timeout(Duration::from_millis(100));
+        assert_eq!(
+            delays(&policy, Duration::ZERO, 4),
+            [ms(40), ms(40), None, None]
+        );
+
+        // rounding up to a tick can push a delay past the timeout.
+        let policy = policy
+            .interval(Duration::from_millis(10))
+            .tick_aligned(true);
+        assert_eq!(delays(&policy, Duration::ZERO, 3), [ms(50), ms(50), None]);
+    }
+
+    #[test]
+    fn keeps_the_queue_full() {
+        let (mut bus, fake) = fake_bus(FakeRobot {
+            capacity: 2,
+            ..Default::default()
+        });
+        let robot = Robot::from_id("r".to_owned());
+
+        let mut scheduler = ActionScheduler::new(&robot).policy(quick());
+        scheduler.extend([MoveDirection::Forward; 3]);
+        scheduler.push(RotationDirection::Left);
+        let reports = scheduler.run(&mut bus).unwrap();
+
+        assert!(scheduler.is_done());
+        assert_eq!(
+            outcomes(&reports),
+            (1..=4)
+                .map(|id| (Some(id), ActionOutcome::Success))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            reports[3].action,
+            RobotAction::Turn(RotationDirection::Left)
+        );
+
+        // the second action goes in before the first one is done, and the rest once those made room.
+        let log = &fake.lock().unwrap().log;
+        assert_eq!(
+            log[..6],
+            ["move 1", "move 2", "result 1", "result 2", "move 3", "turn 4"]
+        );
+    }
+
+    #[test]
+    fn enqueued_actions_report_before_skipped_ones() {
+        let (mut bus, _) = fake_bus(FakeRobot {
+            capacity: 3,
+            failing: vec![2],
+            ..Default::default()
+        });
+        let robot = Robot::from_id("r".to_owned());
+
+        let mut scheduler = ActionScheduler::new(&robot)
+            .policy(quick())
+            .stop_on_failure(true);
+        scheduler.extend([MoveDirection::Forward; 5]);
+        let reports = scheduler.run(&mut bus).unwrap();
+
+        assert_eq!(
+            outcomes(&reports),
+            [
+                (Some(1), ActionOutcome::Success),
+                (Some(2), ActionOutcome::Failed),
+                (Some(3), ActionOutcome::Success),
+                (None, ActionOutcome::Skipped),
+                (None, ActionOutcome::Skipped),
+            ]
+        );
+    }
+
+    #[test]
+    fn failures_dont_stop_the_rest_by_default() {
+        let (mut bus, _) = fake_bus(FakeRobot {
+            capacity: 1,
+            failing: vec![1],
+            ..Default::default()
+        });
+        let robot = Robot::from_id("r".to_owned());
+
+        let mut scheduler = ActionScheduler::new(&robot).policy(quick());
+        scheduler.extend([MoveDirection::Upward; 2]);
+
+        assert_eq!(
+            outcomes(&scheduler.run(&mut bus).unwrap()),
+            [
+                (Some(1), ActionOutcome::Failed),
+                (Some(2), ActionOutcome::Success),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_stuck_queue_times_out() {
+        let (mut bus, _) = fake_bus(FakeRobot {
+            capacity: 1,
+            stuck: true,
+            ..Default::default()
+        });
+        let robot = Robot::from_id("r".to_owned());
+
+        let mut scheduler =
+            ActionScheduler::new(&robot).policy(quick().timeout(Duration::from_millis(20)));
+        scheduler.extend([MoveDirection::Forward; 2]);
+
+        assert_eq!(
+            outcomes(&scheduler.run(&mut bus).unwrap()),
+            [
+                (Some(1), ActionOutcome::TimedOut { action: 1 }),
+                (None, ActionOutcome::NotEnqueued),
+            ]
+        );
+        assert!(scheduler.is_done());
+    }
+
+    #[test]
+    fn poses_are_saved_and_loaded() {
+        let path = std::env::temp_dir().join(format!("miku-robot-{}.pose", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let (mut bus, _) = fake_bus(FakeRobot {
+            capacity: 1,
+            ..Default::default()
+        });
+        let robot = Robot::from_id("r".to_owned());
+
+        let start = Pose::new(BlockPos::new(1, 2, 3), Facing::East);
+        let mut tracked = TrackedRobot::persistent(&robot, &path, start)
+            .unwrap()
+            .policy(quick());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 2 3 east");
+        assert!(tracked.move_wait(&mut bus, MoveDirection::Forward).unwrap());
+
+        // the saved pose wins over the one given.
+        let moved = Pose::new(BlockPos::new(2, 2, 3), Facing::East);
+        let loaded = TrackedRobot::persistent(&robot, &path, start).unwrap();
+        assert_eq!(loaded.pose(), moved);
+
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        assert!(!Path::new(&temp).exists());
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_poses_are_rejected() {
+        let pose = Pose::new(BlockPos::new(-4, 70, 12), Facing::West);
+        assert_eq!(parse_pose(&pose.to_string()).unwrap(), pose);
+        assert_eq!(parse_pose(" -4  70 12\twest\n").unwrap(), pose);
+
+        for saved in [
+            "",
+            "-4 70",
+            "-4 70 12",
+            "-4 70 12 we",
+            "-4 70 12 up",
+            "-4 70.5 12 west",
+            "-4 seventy 12 west",
+            "-4 70 12 west 0",
+        ] {
+            let err = parse_pose(saved).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", saved);
+        }
+    }
+
+    #[test]
+    fn relative_poses_turn_with_the_origin() {
+        let forward = Pose::new(BlockPos::new(0, 1, -2), Facing::North);
+        let right = Pose::new(BlockPos::new(3, 0, 0), Facing::East);
+        let at = |x, y, z, facing| Pose::new(BlockPos::new(x, y, z), facing);
+
+        let origin = at(10, 64, 10, Facing::North);
+        assert_eq!(forward.relative_to(origin), at(10, 65, 8, Facing::North));
+        assert_eq!(right.relative_to(origin), at(13, 64, 10, Facing::East));
+
+        let origin = at(10, 64, 10, Facing::East);
+        assert_eq!(forward.relative_to(origin), at(12, 65, 10, Facing::East));
+        assert_eq!(right.relative_to(origin), at(10, 64, 13, Facing::South));
+
+        let origin = at(10, 64, 10, Facing::South);
+        assert_eq!(forward.relative_to(origin), at(10, 65, 12, Facing::South));
+        assert_eq!(right.relative_to(origin), at(7, 64, 10, Facing::West));
+
+        let origin = at(10, 64, 10, Facing::West);
+        assert_eq!(forward.relative_to(origin), at(8, 65, 10, Facing::West));
+        assert_eq!(right.relative_to(origin), at(10, 64, 7, Facing::North));
+
+        assert_eq!(Pose::default().relative_to(origin), origin);
+    }
+
+    #[test]
+    fn relative_poses_agree_with_actions() {
+        let actions = MoveDirection::ALL
+            .map(RobotAction::Move)
+            .into_iter()
+            .chain(RotationDirection::ALL.map(RobotAction::Turn));
+        let pose = Pose::new(BlockPos::new(1, 2, -3), Facing::South);
+
+        for action in actions {
+            for facing in Facing::ALL {
+                let origin = Pose::new(BlockPos::new(-5, 7, 4), facing);
+                assert_eq!(
+                    pose.apply(action).relative_to(origin),
+                    pose.relative_to(origin).apply(action),
+                    "{:?} facing {}",
+                    action,
+                    facing
+                );
+            }
+        }
+    }
+}
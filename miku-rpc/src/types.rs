@@ -1,8 +1,16 @@
+use crate::nbt::{ParseError, Tag};
 use crate::Response;
+pub use miku_codec::ParseEnumError;
+use miku_codec::{CodecError, DecodeBorrowed, EncodeArg, LazySeq, RawValue};
 use miniserde_miku::de::Visitor;
+use miniserde_miku::json::Value;
 use miniserde_miku::ser::Fragment;
 use miniserde_miku::{make_place, Deserialize, Result as MiniserdeResult, Serialize};
 use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
 
 make_place!(Place);
 
@@ -16,35 +24,137 @@ pub struct DeviceData {
     pub type_names: Vec<String>,
 }
 
+/// A [DeviceData] borrowed from the frame it was decoded from; see [crate::DeviceBus::call_borrowed].
+#[derive(Clone)]
+pub struct DeviceDataRef<'a> {
+    pub device_id: Cow<'a, str>,
+    pub type_names: LazySeq<'a, Cow<'a, str>>,
+}
+
+impl<'a> DecodeBorrowed<'a> for DeviceDataRef<'a> {
+    fn decode(value: RawValue<'a>) -> Result<Self, CodecError> {
+        let mut device_id = None;
+        let mut type_names = None;
+        for entry in value.entries()? {
+            match entry? {
+                ("deviceId", v) => device_id = Some(v.decode()?),
+                ("typeNames", v) => type_names = Some(v.decode()?),
+                _ => {}
+            }
+        }
+
+        Ok(DeviceDataRef {
+            device_id: device_id.ok_or(CodecError::Malformed)?,
+            type_names: type_names.ok_or(CodecError::Malformed)?,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ImportFileInfo {
     pub name: String,
     pub size: u64,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Implements [AsRef<str>], [FromStr], [fmt::Display], [Serialize] and [Deserialize] for an enum, from the string the HLApi uses for each variant.
+/// Parsing ignores case, and deserializing a string that isn't one of them fails instead of being skipped over.
+///
+/// Enums that end with a variant holding a [String], given after the list, deserialize strings they don't know into it instead, so a value added by a newer OC2 still reaches the caller.
+macro_rules! string_enum {
+    ($name:ident, $kind:literal, [$($variant:ident => $s:literal),* $(,)?]) => {
+        impl $name {
+            pub const ALL: [$name; [$($s),*].len()] = [$($name::$variant),*];
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $s,)*
+                }
+            }
+        }
+
+        string_enum!(@shared $name, $kind, |s: &str| s.parse().map_err(|_| miniserde_miku::Error));
+    };
+    ($name:ident, $kind:literal, [$($variant:ident => $s:literal),* $(,)?], $unknown:ident) => {
+        impl $name {
+            /// Every variant but the one for unknown strings.
+            pub const ALL: [$name; [$($s),*].len()] = [$($name::$variant),*];
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                match self {
+                    $($name::$variant => $s,)*
+                    $name::$unknown(s) => s,
+                }
+            }
+        }
+
+        string_enum!(@shared $name, $kind, |s: &str| Ok(s.parse().unwrap_or_else(|_| $name::$unknown(s.to_owned()))));
+    };
+    (@shared $name:ident, $kind:literal, $deserialize:expr) => {
+        impl FromStr for $name {
+            type Err = ParseEnumError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::ALL
+                    .into_iter()
+                    .find(|value| value.as_ref().eq_ignore_ascii_case(s))
+                    .ok_or_else(|| ParseEnumError::new($kind, s))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_ref())
+            }
+        }
+
+        impl Serialize for $name {
+            fn begin(&self) -> Fragment<'_> {
+                Fragment::Str(Cow::Borrowed(self.as_ref()))
+            }
+        }
+
+        impl Visitor for Place<$name> {
+            fn string(&mut self, s: &str) -> MiniserdeResult<()> {
+                let deserialize: fn(&str) -> MiniserdeResult<$name> = $deserialize;
+                self.out = Some(deserialize(s)?);
+                Ok(())
+            }
+        }
+
+        impl Deserialize for $name {
+            fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+                Place::new(out)
+            }
+        }
+    };
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum RobotActionResult {
     Incomplete,
     Success,
     Failure,
+    /// A result this crate doesn't know about.
+    Unknown(String),
 }
 
-impl Visitor for Place<RobotActionResult> {
-    fn string(&mut self, s: &str) -> MiniserdeResult<()> {
-        self.out = match s {
-            "INCOMPLETE" => Some(RobotActionResult::Incomplete),
-            "SUCCESS" => Some(RobotActionResult::Success),
-            "FAILURE" => Some(RobotActionResult::Failure),
-            _ => None,
-        };
-
-        Ok(())
-    }
-}
+string_enum!(RobotActionResult, "robot action result", [
+    Incomplete => "INCOMPLETE",
+    Success => "SUCCESS",
+    Failure => "FAILURE",
+], Unknown);
 
-impl Deserialize for RobotActionResult {
-    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
-        Place::new(out)
+impl RobotActionResult {
+    /// An error for an [RobotActionResult::Unknown] result, for callers that can't tell what it means.
+    pub(crate) fn unknown(result: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            ParseEnumError::new("robot action result", result),
+        )
     }
 }
 
@@ -58,46 +168,748 @@ pub enum MoveDirection {
     Right,
 }
 
-impl AsRef<str> for MoveDirection {
-    fn as_ref(&self) -> &'static str {
-        use MoveDirection::*;
+string_enum!(MoveDirection, "move direction", [
+    Forward => "forward",
+    Backward => "backward",
+    Upward => "upward",
+    Downward => "downward",
+    Left => "left",
+    Right => "right",
+]);
 
-        match self {
-            Forward => "forward",
-            Backward => "backward",
-            Upward => "upward",
-            Downward => "downward",
-            Left => "left",
-            Right => "right",
-        }
+impl EncodeArg for MoveDirection {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode_arg(out)
     }
 }
 
-impl Serialize for MoveDirection {
-    fn begin(&self) -> Fragment {
-        Fragment::Str(Cow::Borrowed(self.as_ref()))
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RotationDirection {
+    Left,
+    Right,
+}
+
+string_enum!(RotationDirection, "rotation direction", [
+    Left => "left",
+    Right => "right",
+]);
+
+impl EncodeArg for RotationDirection {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode_arg(out)
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-pub enum RotationDirection {
+/// A side of a block, either absolute or relative to the way the block is facing. Used by blocks like the redstone interface.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    Up,
+    Down,
+    North,
+    South,
+    West,
+    East,
+    Front,
+    Back,
     Left,
     Right,
 }
 
-impl Serialize for RotationDirection {
-    fn begin(&self) -> Fragment {
-        Fragment::Str(Cow::Borrowed(self.as_ref()))
+string_enum!(Side, "side", [
+    Up => "up",
+    Down => "down",
+    North => "north",
+    South => "south",
+    West => "west",
+    East => "east",
+    Front => "front",
+    Back => "back",
+    Left => "left",
+    Right => "right",
+]);
+
+impl Side {
+    /// Whether this side is relative to the way the block is facing.
+    pub fn is_relative(&self) -> bool {
+        matches!(self, Side::Front | Side::Back | Side::Left | Side::Right)
+    }
+}
+
+impl EncodeArg for Side {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode_arg(out)
+    }
+}
+
+/// A side relative to a robot, for the block and inventory operation modules.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum RobotSide {
+    Front,
+    Up,
+    Down,
+}
+
+string_enum!(RobotSide, "robot side", [
+    Front => "front",
+    Up => "up",
+    Down => "down",
+]);
+
+impl EncodeArg for RobotSide {
+    fn encode_arg(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode_arg(out)
+    }
+}
+
+/// A block side argument: a [Side], or a raw string for a side it doesn't cover.
+pub trait SideArg: EncodeArg {
+    fn as_side_str(&self) -> &str;
+}
+
+/// A robot side argument: a [RobotSide], or a raw string for a side it doesn't cover.
+pub trait RobotSideArg: EncodeArg {
+    fn as_side_str(&self) -> &str;
+}
+
+macro_rules! side_args {
+    ($($side:ty)*) => {$(
+        impl SideArg for $side {
+            fn as_side_str(&self) -> &str {
+                self.as_ref()
+            }
+        }
+
+        impl RobotSideArg for $side {
+            fn as_side_str(&self) -> &str {
+                self.as_ref()
+            }
+        }
+    )*};
+}
+
+side_args!(str String);
+
+impl SideArg for Side {
+    fn as_side_str(&self) -> &str {
+        self.as_ref()
+    }
+}
+
+impl RobotSideArg for RobotSide {
+    fn as_side_str(&self) -> &str {
+        self.as_ref()
+    }
+}
+
+impl<T: SideArg + ?Sized> SideArg for &T {
+    fn as_side_str(&self) -> &str {
+        (**self).as_side_str()
+    }
+}
+
+impl<T: RobotSideArg + ?Sized> RobotSideArg for &T {
+    fn as_side_str(&self) -> &str {
+        (**self).as_side_str()
+    }
+}
+
+/// A stack of items, as described by methods like [crate::wrappers::ItemHandler::get_item_stack_in_slot].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ItemStack {
+    /// The item's registry name, like `minecraft:stone`.
+    pub id: String,
+    #[serde(rename = "Count")]
+    pub count: u32,
+    /// The item's NBT tag, as json. See [ItemStack::nbt] for a typed version.
+    pub tag: Option<Value>,
+}
+
+impl ItemStack {
+    /// Whether this is the stack minecraft uses for an empty slot.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 || self.id == "minecraft:air"
+    }
+
+    /// The item's NBT tag, or an empty compound if it has none.
+    pub fn nbt(&self) -> Result<Tag, ParseError> {
+        nbt(self.tag.as_ref())
+    }
+
+    /// How much damage the item has taken, for items that can take any.
+    pub fn damage(&self) -> u32 {
+        self.nbt()
+            .ok()
+            .and_then(|tag| u32::try_from(tag.get("Damage")?.as_i64()?).ok())
+            .unwrap_or(0)
+    }
+
+    /// The name the item was renamed to, if it was given one.
+    pub fn display_name(&self) -> Option<String> {
+        let tag = self.nbt().ok()?;
+        let name = tag.get("display")?.get("Name")?.as_str()?;
+
+        // names are stored as json text components.
+        let component = miniserde_miku::json::from_str(name).ok()?;
+        let mut text = String::new();
+        component_text(&component, &mut text);
+        Some(text)
+    }
+}
+
+/// An amount of fluid, as described by [crate::wrappers::FluidHandler::get_fluid_in_tank].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FluidStack {
+    /// The fluid's registry name, like `minecraft:water`.
+    #[serde(rename = "FluidName")]
+    pub id: String,
+    /// The amount of fluid, in millibuckets.
+    #[serde(rename = "Amount")]
+    pub amount: u32,
+    /// The fluid's NBT tag, as json. See [FluidStack::nbt] for a typed version.
+    #[serde(rename = "Tag")]
+    pub tag: Option<Value>,
+}
+
+impl FluidStack {
+    /// Whether this is the stack forge uses for an empty tank.
+    pub fn is_empty(&self) -> bool {
+        self.amount == 0 || self.id == "minecraft:empty"
+    }
+
+    /// The fluid's NBT tag, or an empty compound if it has none.
+    pub fn nbt(&self) -> Result<Tag, ParseError> {
+        nbt(self.tag.as_ref())
+    }
+}
+
+// tags usually come as json, but some devices hand them over as SNBT strings instead.
+fn nbt(tag: Option<&Value>) -> Result<Tag, ParseError> {
+    match tag {
+        Some(Value::String(snbt)) => Tag::parse(snbt),
+        Some(tag) => Ok(Tag::from_json(tag)),
+        None => Ok(Tag::default()),
+    }
+}
+
+fn component_text(component: &Value, out: &mut String) {
+    match component {
+        Value::String(text) => out.push_str(text),
+        Value::Array(parts) => parts.iter().for_each(|part| component_text(part, out)),
+        Value::Object(object) => {
+            if let Some(Value::String(text)) = object.get("text") {
+                out.push_str(text);
+            }
+            if let Some(extra) = object.get("extra") {
+                component_text(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The position of a block in the world.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub const ORIGIN: BlockPos = BlockPos::new(0, 0, 0);
+
+    pub const fn new(x: i32, y: i32, z: i32) -> BlockPos {
+        BlockPos { x, y, z }
+    }
+
+    /// Returns the position `distance` blocks away in `direction`.
+    pub fn offset(self, direction: Direction, distance: i32) -> BlockPos {
+        self + direction.unit() * distance
+    }
+
+    /// Returns the position next to this one in `direction`.
+    pub fn neighbor(self, direction: Direction) -> BlockPos {
+        self.offset(direction, 1)
+    }
+
+    /// The number of single block moves it takes to get from here to `other`.
+    pub fn manhattan_distance(self, other: BlockPos) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y) + self.z.abs_diff(other.z)
+    }
+}
+
+impl fmt::Display for BlockPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+impl Add for BlockPos {
+    type Output = BlockPos;
+
+    fn add(self, other: BlockPos) -> BlockPos {
+        BlockPos::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for BlockPos {
+    type Output = BlockPos;
+
+    fn sub(self, other: BlockPos) -> BlockPos {
+        BlockPos::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<i32> for BlockPos {
+    type Output = BlockPos;
+
+    fn mul(self, n: i32) -> BlockPos {
+        BlockPos::new(self.x * n, self.y * n, self.z * n)
+    }
+}
+
+/// An absolute direction in the world. Like in minecraft, north is towards negative z and east towards positive x.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    North,
+    South,
+    West,
+    East,
+}
+
+string_enum!(Direction, "direction", [
+    Up => "up",
+    Down => "down",
+    North => "north",
+    South => "south",
+    West => "west",
+    East => "east",
+]);
+
+impl Direction {
+    /// Returns the offset of a single step in this direction.
+    pub fn unit(self) -> BlockPos {
+        match self {
+            Direction::Up => BlockPos::new(0, 1, 0),
+            Direction::Down => BlockPos::new(0, -1, 0),
+            Direction::North => BlockPos::new(0, 0, -1),
+            Direction::South => BlockPos::new(0, 0, 1),
+            Direction::West => BlockPos::new(-1, 0, 0),
+            Direction::East => BlockPos::new(1, 0, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+
+    /// Returns the horizontal facing for this direction, or None for up and down.
+    pub fn facing(self) -> Option<Facing> {
+        match self {
+            Direction::North => Some(Facing::North),
+            Direction::South => Some(Facing::South),
+            Direction::West => Some(Facing::West),
+            Direction::East => Some(Facing::East),
+            Direction::Up | Direction::Down => None,
+        }
+    }
+
+    /// Returns the way something facing `facing` has to move to go in this direction.
+    pub fn to_relative(self, facing: Facing) -> MoveDirection {
+        match self.facing() {
+            None if self == Direction::Up => MoveDirection::Upward,
+            None => MoveDirection::Downward,
+            Some(f) if f == facing => MoveDirection::Forward,
+            Some(f) if f == facing.opposite() => MoveDirection::Backward,
+            Some(f) if f == facing.left() => MoveDirection::Left,
+            Some(_) => MoveDirection::Right,
+        }
+    }
+}
+
+impl From<Direction> for Side {
+    fn from(direction: Direction) -> Side {
+        match direction {
+            Direction::Up => Side::Up,
+            Direction::Down => Side::Down,
+            Direction::North => Side::North,
+            Direction::South => Side::South,
+            Direction::West => Side::West,
+            Direction::East => Side::East,
+        }
     }
 }
 
-impl AsRef<str> for RotationDirection {
-    fn as_ref(&self) -> &'static str {
+/// The horizontal direction something, like a robot, is facing.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+string_enum!(Facing, "facing", [
+    North => "north",
+    East => "east",
+    South => "south",
+    West => "west",
+]);
+
+impl Facing {
+    /// Returns the facing after turning once in `rotation`.
+    pub fn rotate(self, rotation: RotationDirection) -> Facing {
+        match rotation {
+            RotationDirection::Left => self.left(),
+            RotationDirection::Right => self.right(),
+        }
+    }
+
+    /// Returns the facing a quarter turn counterclockwise from this one.
+    pub fn left(self) -> Facing {
+        match self {
+            Facing::North => Facing::West,
+            Facing::West => Facing::South,
+            Facing::South => Facing::East,
+            Facing::East => Facing::North,
+        }
+    }
+
+    /// Returns the facing a quarter turn clockwise from this one.
+    pub fn right(self) -> Facing {
+        self.left().opposite()
+    }
+
+    pub fn opposite(self) -> Facing {
+        self.left().left()
+    }
+
+    /// Returns the shortest list of turns that gets from this facing to `target`.
+    pub fn turns_to(self, target: Facing) -> &'static [RotationDirection] {
         use RotationDirection::*;
 
+        if target == self {
+            &[]
+        } else if target == self.left() {
+            &[Left]
+        } else if target == self.right() {
+            &[Right]
+        } else {
+            &[Right, Right]
+        }
+    }
+
+    pub fn direction(self) -> Direction {
+        match self {
+            Facing::North => Direction::North,
+            Facing::East => Direction::East,
+            Facing::South => Direction::South,
+            Facing::West => Direction::West,
+        }
+    }
+}
+
+impl From<Facing> for Direction {
+    fn from(facing: Facing) -> Direction {
+        facing.direction()
+    }
+}
+
+impl MoveDirection {
+    /// Returns the absolute direction this move goes in, for something facing `facing`.
+    pub fn to_absolute(self, facing: Facing) -> Direction {
+        match self {
+            MoveDirection::Forward => facing.direction(),
+            MoveDirection::Backward => facing.opposite().direction(),
+            MoveDirection::Left => facing.left().direction(),
+            MoveDirection::Right => facing.right().direction(),
+            MoveDirection::Upward => Direction::Up,
+            MoveDirection::Downward => Direction::Down,
+        }
+    }
+}
+
+impl RobotSide {
+    /// Returns the absolute direction of this side, for a robot facing `facing`.
+    pub fn to_absolute(self, facing: Facing) -> Direction {
+        match self {
+            RobotSide::Front => facing.direction(),
+            RobotSide::Up => Direction::Up,
+            RobotSide::Down => Direction::Down,
+        }
+    }
+}
+
+impl Side {
+    /// Returns the absolute direction of this side, for a block facing `facing`.
+    pub fn to_absolute(self, facing: Facing) -> Direction {
         match self {
-            Left => "left",
-            Right => "right",
+            Side::Up => Direction::Up,
+            Side::Down => Direction::Down,
+            Side::North => Direction::North,
+            Side::South => Direction::South,
+            Side::West => Direction::West,
+            Side::East => Direction::East,
+            Side::Front => facing.direction(),
+            Side::Back => facing.opposite().direction(),
+            Side::Left => facing.left().direction(),
+            Side::Right => facing.right().direction(),
+        }
+    }
+}
+
+/// A box of blocks, including both of its corners.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Cuboid {
+    pub min: BlockPos,
+    pub max: BlockPos,
+}
+
+impl Cuboid {
+    /// Creates the smallest cuboid containing both corners, in any order.
+    pub fn new(a: BlockPos, b: BlockPos) -> Cuboid {
+        Cuboid {
+            min: BlockPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: BlockPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn contains(&self, pos: BlockPos) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    /// Returns the number of blocks along each axis.
+    pub fn size(&self) -> BlockPos {
+        self.max - self.min + BlockPos::new(1, 1, 1)
+    }
+
+    /// Returns the number of blocks in the cuboid.
+    pub fn volume(&self) -> u64 {
+        let size = self.size();
+        size.x as u64 * size.y as u64 * size.z as u64
+    }
+
+    /// Returns the smallest cuboid containing both this one and `pos`.
+    pub fn including(&self, pos: BlockPos) -> Cuboid {
+        Cuboid::new(
+            BlockPos::new(
+                self.min.x.min(pos.x),
+                self.min.y.min(pos.y),
+                self.min.z.min(pos.z),
+            ),
+            BlockPos::new(
+                self.max.x.max(pos.x),
+                self.max.y.max(pos.y),
+                self.max.z.max(pos.z),
+            ),
+        )
+    }
+
+    /// Returns the closest position in the cuboid to `pos`.
+    pub fn clamp(&self, pos: BlockPos) -> BlockPos {
+        BlockPos::new(
+            pos.x.clamp(self.min.x, self.max.x),
+            pos.y.clamp(self.min.y, self.max.y),
+            pos.z.clamp(self.min.z, self.max.z),
+        )
+    }
+
+    /// Iterates over every position in the cuboid, x first, then z, then y.
+    pub fn positions(&self) -> impl Iterator<Item = BlockPos> {
+        let Cuboid { min, max } = *self;
+        (min.y..=max.y).flat_map(move |y| {
+            (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| BlockPos::new(x, y, z)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayString;
+    use miniserde_miku::json;
+
+    fn to_json(value: &impl Serialize) -> String {
+        let mut out = ArrayString::<64>::new();
+        json::to_string::<_, 64, 4>(value, &mut out);
+        out.to_string()
+    }
+
+    #[test]
+    fn sides_round_trip() {
+        for side in Side::ALL {
+            assert_eq!(side.to_string().parse::<Side>().unwrap(), side);
+            assert_eq!(json::from_str::<Side>(&to_json(&side)).unwrap(), side);
+        }
+        for side in RobotSide::ALL {
+            assert_eq!(side.to_string().parse::<RobotSide>().unwrap(), side);
+            assert_eq!(json::from_str::<RobotSide>(&to_json(&side)).unwrap(), side);
         }
+
+        assert_eq!(to_json(&Side::North), r#""north""#);
+        assert_eq!("FRONT".parse::<RobotSide>().unwrap(), RobotSide::Front);
+        assert_eq!(json::from_str::<Side>(r#""Left""#).unwrap(), Side::Left);
+        // robots only have three sides.
+        assert_eq!(
+            "north".parse::<RobotSide>().unwrap_err().to_string(),
+            r#"unknown robot side "north""#
+        );
+        assert!(json::from_str::<RobotSide>(r#""north""#).is_err());
+    }
+
+    #[test]
+    fn unknown_action_results_are_kept() {
+        assert_eq!(
+            json::from_str::<RobotActionResult>(r#""SUCCESS""#).unwrap(),
+            RobotActionResult::Success
+        );
+        let result = json::from_str::<RobotActionResult>(r#""CANCELLED""#).unwrap();
+        assert_eq!(result, RobotActionResult::Unknown("CANCELLED".to_owned()));
+        assert_eq!(to_json(&result), r#""CANCELLED""#);
+
+        // only deserializing falls back; parsing still fails, like for any other enum.
+        assert!("CANCELLED".parse::<RobotActionResult>().is_err());
+        assert!(!RobotActionResult::ALL.contains(&result));
+    }
+
+    fn stack(json: &str) -> ItemStack {
+        json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn empty_stacks() {
+        assert!(stack(r#"{"id":"minecraft:air","Count":1}"#).is_empty());
+        assert!(stack(r#"{"id":"minecraft:stone","Count":0}"#).is_empty());
+        assert!(!stack(r#"{"id":"minecraft:stone","Count":64}"#).is_empty());
+
+        let fluid: FluidStack =
+            json::from_str(r#"{"FluidName":"minecraft:empty","Amount":0}"#).unwrap();
+        assert!(fluid.is_empty());
+        let fluid: FluidStack =
+            json::from_str(r#"{"FluidName":"minecraft:water","Amount":1000}"#).unwrap();
+        assert!(!fluid.is_empty());
+    }
+
+    #[test]
+    fn item_damage() {
+        let pickaxe = stack(r#"{"id":"minecraft:iron_pickaxe","Count":1,"tag":{"Damage":42}}"#);
+        assert_eq!(pickaxe.damage(), 42);
+        // some devices send tags as SNBT.
+        let pickaxe = stack(r#"{"id":"minecraft:iron_pickaxe","Count":1,"tag":"{Damage:7}"}"#);
+        assert_eq!(pickaxe.damage(), 7);
+
+        assert_eq!(stack(r#"{"id":"minecraft:stone","Count":1}"#).damage(), 0);
+        assert_eq!(
+            stack(r#"{"id":"minecraft:stone","Count":1,"tag":{"Damage":-3}}"#).damage(),
+            0
+        );
+        assert_eq!(
+            stack(r#"{"id":"minecraft:stone","Count":1,"tag":"{Damage:"}"#).damage(),
+            0
+        );
+    }
+
+    #[test]
+    fn item_display_names() {
+        let named = stack(
+            r#"{"id":"minecraft:diamond_pickaxe","Count":1,"tag":{"display":{"Name":"{\"text\":\"Big \",\"extra\":[\"Dig\",{\"text\":\"ger\"}]}"}}}"#,
+        );
+        assert_eq!(named.display_name().as_deref(), Some("Big Digger"));
+        let named =
+            stack(r#"{"id":"minecraft:stone","Count":1,"tag":{"display":{"Name":"\"Rock\""}}}"#);
+        assert_eq!(named.display_name().as_deref(), Some("Rock"));
+
+        assert_eq!(
+            stack(r#"{"id":"minecraft:stone","Count":1}"#).display_name(),
+            None
+        );
+        let unnamed = stack(r#"{"id":"minecraft:stone","Count":1,"tag":{"display":{"Lore":[]}}}"#);
+        assert_eq!(unnamed.display_name(), None);
+    }
+
+    #[test]
+    fn facing_rotation() {
+        assert_eq!(Facing::North.right(), Facing::East);
+        assert_eq!(Facing::North.left(), Facing::West);
+        assert_eq!(Facing::East.opposite(), Facing::West);
+
+        for facing in Facing::ALL {
+            assert_eq!(facing.left().right(), facing);
+            assert_eq!(
+                facing
+                    .rotate(RotationDirection::Right)
+                    .rotate(RotationDirection::Left),
+                facing
+            );
+
+            for target in Facing::ALL {
+                let turns = facing.turns_to(target);
+                let turned = turns
+                    .iter()
+                    .fold(facing, |facing, &turn| facing.rotate(turn));
+                assert_eq!(turned, target);
+                assert!(turns.len() <= 2);
+            }
+        }
+    }
+
+    #[test]
+    fn directions() {
+        let pos = BlockPos::new(3, 64, -2);
+        assert_eq!(pos.neighbor(Direction::North), BlockPos::new(3, 64, -3));
+        assert_eq!(pos.neighbor(Direction::East), BlockPos::new(4, 64, -2));
+        assert_eq!(pos.offset(Direction::Down, 4), BlockPos::new(3, 60, -2));
+        assert_eq!(pos.manhattan_distance(BlockPos::ORIGIN), 69);
+
+        for direction in Direction::ALL {
+            assert_eq!(pos.neighbor(direction).neighbor(direction.opposite()), pos);
+            assert_eq!(pos.manhattan_distance(pos.neighbor(direction)), 1);
+
+            for facing in Facing::ALL {
+                assert_eq!(direction.to_relative(facing).to_absolute(facing), direction);
+            }
+        }
+
+        assert_eq!(
+            Direction::West.to_relative(Facing::North),
+            MoveDirection::Left
+        );
+        assert_eq!(
+            RobotSide::Front.to_absolute(Facing::South),
+            Direction::South
+        );
+        assert_eq!(Side::Right.to_absolute(Facing::West), Direction::North);
+    }
+
+    #[test]
+    fn cuboids() {
+        let cuboid = Cuboid::new(BlockPos::new(2, 10, -1), BlockPos::new(-1, 8, 3));
+        assert_eq!(cuboid.min, BlockPos::new(-1, 8, -1));
+        assert_eq!(cuboid.max, BlockPos::new(2, 10, 3));
+        assert_eq!(cuboid.size(), BlockPos::new(4, 3, 5));
+        assert_eq!(cuboid.volume(), 60);
+        assert_eq!(cuboid.positions().count(), 60);
+        assert!(cuboid.positions().all(|pos| cuboid.contains(pos)));
+
+        assert!(cuboid.contains(cuboid.min) && cuboid.contains(cuboid.max));
+        assert!(!cuboid.contains(BlockPos::new(3, 9, 0)));
+        assert!(!cuboid.contains(BlockPos::new(0, 7, 0)));
+
+        let far = BlockPos::new(10, 0, 0);
+        assert_eq!(cuboid.clamp(far), BlockPos::new(2, 8, 0));
+        let grown = cuboid.including(far);
+        assert!(grown.contains(far) && grown.contains(cuboid.min));
+        assert_eq!(grown.volume(), 12 * 11 * 5);
     }
 }
@@ -1,11 +1,17 @@
-use crate::types::{ImportFileInfo, MoveDirection, RobotActionResult, RotationDirection};
-use miku_macros::{define_device, rpc};
+use crate::robot::{ActionOutcome, WaitPolicy, Waiter};
+#[cfg(doc)]
+use crate::types::Side;
+use crate::types::{
+    DeviceData, FluidStack, ImportFileInfo, ItemStack, MoveDirection, RobotActionResult, RobotSide,
+    RobotSideArg, RotationDirection, SideArg,
+};
+use crate::{Call, EncodeArgs, InvokeWriter};
+use miku_codec::RawValue;
+use miku_macros::{device, rpc, rpc_interface};
 use miniserde_miku::Deserialize;
+use std::fmt;
 use std::io;
-use std::thread;
-use std::time::Duration;
-
-const ROBOT_ACTION_SLEEP: Duration = Duration::from_millis(100);
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 /// An opencomputers HLApi device.
 pub trait RPCDevice {
@@ -14,14 +20,99 @@ pub trait RPCDevice {
 
     /// Create a device wrapper from an id.
     fn from_id(id: String) -> Self;
+
+    /// Returns the cache that frames for this device's zero-argument methods are kept in. Wrappers made with `#[device]` each have their own; without one, frames get rendered on every call.
+    fn frame_cache(&self) -> Option<&FrameCache> {
+        None
+    }
+
+    /// Wraps the same device as another type, to use capabilities this wrapper doesn't have.
+    fn as_device<T: RPCDevice>(&self) -> T
+    where
+        Self: Sized,
+    {
+        T::from_id(self.id().to_owned())
+    }
+}
+
+type Frames = Vec<(&'static str, Arc<[u8]>)>;
+
+/// Frames for one device's zero-argument methods, rendered on their first call, then reused for every call after that.
+#[derive(Default)]
+pub struct FrameCache {
+    // a device only has a handful of methods, so a list is quicker to search than a map.
+    frames: Mutex<Frames>,
+}
+
+impl FrameCache {
+    pub const fn new() -> FrameCache {
+        FrameCache {
+            frames: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the frame for a method, using `render` to build it if it isn't cached yet.
+    pub fn frame(&self, method_name: &'static str, render: impl FnOnce(&mut Vec<u8>)) -> Arc<[u8]> {
+        let mut frames = self.lock();
+        if let Some((_, frame)) = frames.iter().find(|(name, _)| *name == method_name) {
+            return frame.clone();
+        }
+
+        let mut frame = Vec::new();
+        render(&mut frame);
+        let frame: Arc<[u8]> = frame.into();
+        frames.push((method_name, frame.clone()));
+        frame
+    }
+
+    /// Forgets every frame rendered so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Frames> {
+        // a panic while this was held can't have left the list half-updated, so a poisoned lock is still good to use.
+        self.frames.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// a clone wraps the same device, so the frames are still good for it.
+impl Clone for FrameCache {
+    fn clone(&self) -> Self {
+        FrameCache {
+            frames: Mutex::new(self.lock().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for FrameCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameCache")
+            .field("frames", &self.lock().len())
+            .finish()
+    }
 }
 
 /// A HLApi device that has an identity - like "redstone". This is used for the [crate::DeviceBus::wrap] method.
 pub trait IdentifiedDevice: RPCDevice {
+    /// The type name this device is usually known by.
     const IDENTITY: &'static str;
+
+    /// Every type name this device can show up under.
+    const IDENTITIES: &'static [&'static str] = &[Self::IDENTITY];
+
+    /// Type names a device needs to have all of, on top of one of [IdentifiedDevice::IDENTITIES].
+    const REQUIRES: &'static [&'static str] = &[];
+
+    /// Whether a device from the device list can be wrapped as this type.
+    fn matches(device: &DeviceData) -> bool {
+        let has = |identity: &&str| device.type_names.iter().any(|name| name == identity);
+        Self::IDENTITIES.iter().any(has) && Self::REQUIRES.iter().all(has)
+    }
 }
 
 /// An interface that allows for interacting with an energy storage device.
+#[rpc_interface]
 pub trait EnergyStorage: RPCDevice {
     #[rpc("getEnergyStored")]
     fn get_energy_stored() -> i32;
@@ -37,6 +128,7 @@ pub trait EnergyStorage: RPCDevice {
 }
 
 /// An interface that allows for interacting with items.
+#[rpc_interface]
 pub trait ItemHandler: RPCDevice {
     #[rpc("getItemSlotCount")]
     fn get_item_slot_count() -> i32;
@@ -46,9 +138,20 @@ pub trait ItemHandler: RPCDevice {
 
     #[rpc("getItemStackInSlot")]
     fn get_item_stack_in_slot<T: Deserialize>(slot: i32) -> T;
+
+    /// Same as get_item_stack_in_slot(), but as an [ItemStack], with empty slots turned into None.
+    fn item_stack_in_slot(
+        &self,
+        bus: &mut crate::DeviceBus,
+        slot: i32,
+    ) -> io::Result<Option<ItemStack>> {
+        let stack: Option<ItemStack> = self.get_item_stack_in_slot(bus, slot)?;
+        Ok(stack.filter(|stack| !stack.is_empty()))
+    }
 }
 
 /// An interface that allows for interacting with fluid tanks.
+#[rpc_interface]
 pub trait FluidHandler: RPCDevice {
     #[rpc("getFluidTanks")]
     fn get_fluid_tanks() -> i32;
@@ -58,24 +161,38 @@ pub trait FluidHandler: RPCDevice {
 
     #[rpc("getFluidInTank")]
     fn get_fluid_in_tank<T: Deserialize>(tank: i32) -> T;
+
+    /// Same as get_fluid_in_tank(), but as a [FluidStack], with empty tanks turned into None.
+    fn fluid_stack_in_tank(
+        &self,
+        bus: &mut crate::DeviceBus,
+        tank: i32,
+    ) -> io::Result<Option<FluidStack>> {
+        let stack: Option<FluidStack> = self.get_fluid_in_tank(bus, tank)?;
+        Ok(stack.filter(|stack| !stack.is_empty()))
+    }
 }
 
 /// An interface that allows for interacting with redstone signals.
+///
+/// The side parameter in the following methods is a [Side], or the name of one.
+#[rpc_interface]
 pub trait RedstoneInterface: RPCDevice {
     #[rpc("getRedstoneInput", docs = "block/redstone_interface.md")]
     /// gets the received redstone signal for the specified side.
-    fn get_redstone_input(side: &str) -> i32;
+    fn get_redstone_input(side: impl SideArg) -> i32;
 
     #[rpc("getRedstoneOutput", docs = "block/redstone_interface.md")]
     /// gets the emitted redstone signal for the specified side.
-    fn get_redstone_output(side: &str) -> i32;
+    fn get_redstone_output(side: impl SideArg) -> i32;
 
     #[rpc("setRedstoneOutput", docs = "block/redstone_interface.md")]
     /// sets the emitted redstone signal for the specified side.
-    fn set_redstone_output(side: &str, val: i32);
+    fn set_redstone_output(side: impl SideArg, val: i32);
 }
 
 /// A device capable of playing sounds.
+#[rpc_interface]
 pub trait SoundInterface: RPCDevice {
     /// returns a list of available sound effects matching the given name. Note that the number of results is limited, so overly generic queries will result in truncated results.
     #[rpc("findSound", docs = "item/sound_card.md")]
@@ -84,9 +201,14 @@ pub trait SoundInterface: RPCDevice {
     #[rpc("playSound", docs = "item/sound_card.md")]
     /// plays back the sound effect with the specified name.
     fn play_sound(name: &str, volume: f64, pitch: f64);
+
+    #[rpc("playSound", docs = "item/sound_card.md")]
+    /// plays back the sound effect with the specified name, at its default volume and pitch.
+    fn play_default_sound(name: &str);
 }
 
 /// An interface that allows exporting and importing files.
+#[rpc_interface]
 pub trait FileImportExport: RPCDevice {
     #[rpc("requestImportFile")]
     fn request_import_file() -> bool;
@@ -97,6 +219,27 @@ pub trait FileImportExport: RPCDevice {
     #[rpc("readImportFile")]
     fn read_import_file() -> Option<Vec<u8>>;
 
+    /// Same as read_import_file(), but parses the chunk straight into `buf` instead of allocating a new one, replacing what was in it.
+    /// Returns false once there is nothing left to read.
+    fn read_import_file_into(
+        &self,
+        bus: &mut crate::DeviceBus,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<bool> {
+        let id = self.id();
+        let response: crate::Response<Option<RawValue>> =
+            bus.call_with_borrowed(|frame| InvokeWriter::new(frame, "readImportFile").finish(id))?;
+
+        buf.clear();
+        match response.data {
+            Some(data) => {
+                data.bytes_into(buf)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     #[rpc("beginExportFile")]
     fn begin_export_file(name: &str);
 
@@ -112,17 +255,26 @@ pub trait FileImportExport: RPCDevice {
 
 /// An interface that allows for the manipulation of blocks in the world.
 ///
-/// The side parameter in the following methods represents a direction from the perspective of the robot. Valid values are: "front", "up" and "down"
+/// The side parameter in the following methods represents a direction from the perspective of the robot, as a [RobotSide] or one of "front", "up" and "down".
+#[rpc_interface]
 pub trait BlockOperationsInterface: RPCDevice {
     #[rpc("excavate", docs = "item/block_operations_module.md")]
     /// tries to break a block in the specified direction. Collected blocks will be inserted starting at the currently selected inventory slot. If the selected slot is full, the next slot will be used, and so on. If the inventory has no space for the dropped block, it will drop into the world.
     /// Returns whether the operation was successful.
-    fn excavate(side: &str) -> bool;
+    fn excavate(side: impl RobotSideArg) -> bool;
+
+    #[rpc("excavate", docs = "item/block_operations_module.md")]
+    /// Same as excavate(), but the side can be left out, which breaks the block in front of the robot.
+    fn excavate_at(side: Option<RobotSide>) -> bool;
 
     #[rpc("place", docs = "item/block_operations_module.md")]
     /// tries to place a block in the specified direction. Blocks will be placed from the currently selected inventory slot. If the slot is empty, no block will be placed.
     /// Returns whether the operation was successful.
-    fn place(side: &str) -> bool;
+    fn place(side: impl RobotSideArg) -> bool;
+
+    #[rpc("place", docs = "item/block_operations_module.md")]
+    /// Same as place(), but the side can be left out, which places the block in front of the robot.
+    fn place_at(side: Option<RobotSide>) -> bool;
 
     #[rpc("durability", docs = "item/block_operations_module.md")]
     /// returns the remaining durability of the module's excavation tool. Once the durability has reached zero, no further excavation operations can be performed until it is repaired.
@@ -136,7 +288,8 @@ pub trait BlockOperationsInterface: RPCDevice {
 
 /// An interface that allows for the manipulation of inventories in the world.
 ///
-/// The side parameter in the following methods represents a direction from the perspective of the robot. Valid values are: "front", "up" and "down"
+/// The side parameter in the following methods represents a direction from the perspective of the robot, as a [RobotSide] or one of "front", "up" and "down".
+#[rpc_interface]
 pub trait InventoryOperationsInterface: RPCDevice {
     #[rpc("move", docs = "item/inventory_operations.md")]
     /// tries to move the specified number of items from one robot inventory slot to another.
@@ -145,25 +298,26 @@ pub trait InventoryOperationsInterface: RPCDevice {
     #[rpc("drop", docs = "item/inventory_operations.md")]
     /// tries to drop items from the specified slot in the specified direction. It will drop items either into an inventory, or the world if no inventory is present.
     /// Returns the number of items dropped.
-    fn drop(count: i32, side: &str) -> i32;
+    fn drop(count: i32, side: impl RobotSideArg) -> i32;
 
     #[rpc("dropInto", docs = "item/inventory_operations.md")]
     /// tries to drop items from the specified slot into the specified slot of an inventory in the specified direction. It will only drop items into an inventory.
     /// Returns the number of items dropped.
-    fn drop_into(into: i32, count: i32, side: &str) -> i32;
+    fn drop_into(into: i32, count: i32, side: impl RobotSideArg) -> i32;
 
     #[rpc("take", docs = "item/inventory_operations.md")]
     /// tries to take the specified number of items from the specified direction. It will take items from either an inventory, or the world if no inventory is present.
     /// Returns the number of items taken.
-    fn take(count: i32, side: &str) -> i32;
+    fn take(count: i32, side: impl RobotSideArg) -> i32;
 
     #[rpc("take_from", docs = "item/inventory_operations.md")]
     /// tries to take the specified number of items from the specified slot from an inventory in the specified direction. It will only take items from an inventory.
     /// Returns the number of items taken.
-    fn take_from(from: i32, count: i32, side: &str) -> i32;
+    fn take_from(from: i32, count: i32, side: impl RobotSideArg) -> i32;
 }
 
 /// A robit!
+#[rpc_interface]
 pub trait RobotInterface: RPCDevice {
     #[rpc("getEnergyStored", docs = "item/robot.md")]
     /// returns the current amount of energy stored in the robot's internal energy storage.
@@ -185,6 +339,16 @@ pub trait RobotInterface: RPCDevice {
     /// gets a description of the item in the specified slot.
     fn get_stack_in_slot<T: Deserialize>(slot: i32) -> T;
 
+    /// Same as get_stack_in_slot(), but as an [ItemStack], with empty slots turned into None.
+    fn item_stack_in_slot(
+        &self,
+        bus: &mut crate::DeviceBus,
+        slot: i32,
+    ) -> io::Result<Option<ItemStack>> {
+        let stack: Option<ItemStack> = self.get_stack_in_slot(bus, slot)?;
+        Ok(stack.filter(|stack| !stack.is_empty()))
+    }
+
     #[rpc("getLastActionId", docs = "item/robot.md")]
     /// returns the opaque id of the last enqueued action. Call this after a successful move_async() or turn_async() call to obtain the id associated with the enqueued action.
     fn get_last_action_id() -> i32;
@@ -197,23 +361,24 @@ pub trait RobotInterface: RPCDevice {
     /// returns the result of the action with the specified id. Action ids can be obtained from get_last_action_id(). Only a limited number of past action results are available.
     fn get_action_result(id: i32) -> RobotActionResult;
 
-    #[rpc("move", docs = "item/robot.md")]
+    #[rpc("getActionResult", docs = "item/robot.md")]
+    /// Same as get_action_result(), but gives None for ids the robot doesn't have a result for, either because their result expired or because they were never enqueued.
+    fn try_get_action_result(id: i32) -> Option<RobotActionResult>;
+
+    #[rpc("move", docs = "item/robot.md", async_name = "enqueue_move_async")]
     /// tries to enqueue a movement action in the specified direction.
     /// Returns whether the action was enqueued successfully.
     fn move_async(direction: MoveDirection) -> bool;
 
-    #[rpc("turn", docs = "item/robot.md")]
+    #[rpc("turn", docs = "item/robot.md", async_name = "enqueue_turn_async")]
     /// tries to enqueue a turn action in the specified direction.
     /// Returns whether the action was enqueued successfully.
     fn turn_async(direction: RotationDirection) -> bool;
 
     /// Same as move_async(), but waits until action is succesfully enqueued and completed.
     fn move_wait(&self, bus: &mut crate::DeviceBus, direction: MoveDirection) -> io::Result<bool> {
-        while !self.move_async(bus, direction)? {
-            thread::sleep(ROBOT_ACTION_SLEEP)
-        }
-        let id = self.get_last_action_id(bus)?;
-        self.wait_for_action(bus, id)
+        self.move_with(bus, direction, &WaitPolicy::default())?
+            .succeeded()
     }
 
     /// Same as turn_async(), but waits until action is succesfully enqueued and completed.
@@ -222,55 +387,569 @@ pub trait RobotInterface: RPCDevice {
         bus: &mut crate::DeviceBus,
         direction: RotationDirection,
     ) -> io::Result<bool> {
+        self.turn_with(bus, direction, &WaitPolicy::default())?
+            .succeeded()
+    }
+
+    /// Waits for an action to complete; returns if it was sucessful or not.
+    /// Fails if the action's result can't be found, like when it has already expired.
+    fn wait_for_action(&self, bus: &mut crate::DeviceBus, action: i32) -> io::Result<bool> {
+        self.wait_for_action_with(bus, action, &WaitPolicy::default())?
+            .succeeded()
+    }
+
+    /// Same as move_wait(), but waits according to `policy`, and tells why the move didn't succeed if it didn't.
+    fn move_with(
+        &self,
+        bus: &mut crate::DeviceBus,
+        direction: MoveDirection,
+        policy: &WaitPolicy,
+    ) -> io::Result<ActionOutcome> {
+        let mut waiter = policy.start();
+        while !self.move_async(bus, direction)? {
+            if !waiter.sleep() {
+                return Ok(ActionOutcome::NotEnqueued);
+            }
+        }
+        let id = self.get_last_action_id(bus)?;
+        self.poll_action(bus, id, &mut waiter)
+    }
+
+    /// Same as turn_wait(), but waits according to `policy`, and tells why the turn didn't succeed if it didn't.
+    fn turn_with(
+        &self,
+        bus: &mut crate::DeviceBus,
+        direction: RotationDirection,
+        policy: &WaitPolicy,
+    ) -> io::Result<ActionOutcome> {
+        let mut waiter = policy.start();
         while !self.turn_async(bus, direction)? {
-            thread::sleep(ROBOT_ACTION_SLEEP)
+            if !waiter.sleep() {
+                return Ok(ActionOutcome::NotEnqueued);
+            }
         }
         let id = self.get_last_action_id(bus)?;
-        self.wait_for_action(bus, id)
+        self.poll_action(bus, id, &mut waiter)
     }
 
-    /// Waits for an action to complete; returns if it was sucessful or not.
-    fn wait_for_action(&self, bus: &mut crate::DeviceBus, action: i32) -> io::Result<bool> {
-        let result = loop {
-            let result = self.get_action_result(bus, action)?;
-            match result {
-                RobotActionResult::Success | RobotActionResult::Failure => break result,
-                RobotActionResult::Incomplete => thread::sleep(ROBOT_ACTION_SLEEP),
+    /// Same as wait_for_action(), but waits according to `policy`, and tells why the action didn't succeed if it didn't.
+    fn wait_for_action_with(
+        &self,
+        bus: &mut crate::DeviceBus,
+        action: i32,
+        policy: &WaitPolicy,
+    ) -> io::Result<ActionOutcome> {
+        self.poll_action(bus, action, &mut policy.start())
+    }
+
+    /// Polls an action's result until it's done, or until `waiter` runs out of time.
+    fn poll_action(
+        &self,
+        bus: &mut crate::DeviceBus,
+        action: i32,
+        waiter: &mut Waiter<'_>,
+    ) -> io::Result<ActionOutcome> {
+        loop {
+            match self.try_get_action_result(bus, action)? {
+                Some(RobotActionResult::Success) => return Ok(ActionOutcome::Success),
+                Some(RobotActionResult::Failure) => return Ok(ActionOutcome::Failed),
+                Some(RobotActionResult::Incomplete) => {}
+                Some(RobotActionResult::Unknown(result)) => {
+                    return Err(RobotActionResult::unknown(&result))
+                }
+                // ids count up, so one at or below the last one was real, and its result got dropped.
+                None if action <= self.get_last_action_id(bus)? => {
+                    return Ok(ActionOutcome::Expired { action })
+                }
+                None => return Ok(ActionOutcome::UnknownAction { action }),
             }
-        };
 
-        Ok(result == RobotActionResult::Success)
+            if !waiter.sleep() {
+                return Ok(ActionOutcome::TimedOut { action });
+            }
+        }
+    }
+}
+
+/// A device capable of interacting with redstone
+#[device(identities = ["redstone"], capabilities = [RedstoneInterface])]
+#[derive(Debug)]
+pub struct RedstoneDevice;
+
+/// A device capable of playing sounds
+#[device(identities = ["sound"], capabilities = [SoundInterface])]
+#[derive(Debug)]
+pub struct SoundCard;
+
+/// A device capable of importing and exporting files
+#[device(identities = ["file_import_export"], capabilities = [FileImportExport])]
+#[derive(Debug)]
+pub struct FileImportExportCard;
+
+/// A robit!
+#[device(identities = ["robot"], capabilities = [RobotInterface])]
+#[derive(Debug)]
+pub struct Robot;
+
+/// A device capable of manipulating blocks in the world
+#[device(identities = ["block_operations"], capabilities = [BlockOperationsInterface])]
+#[derive(Debug)]
+pub struct BlockOperationsModule;
+
+/// A device capable of manipulating inventories in the world
+#[device(
+    identities = ["inventory_operations"],
+    capabilities = [InventoryOperationsInterface]
+)]
+#[derive(Debug)]
+pub struct InventoryOperationsModule;
+
+/// A block that stores energy, like an energy cell
+#[device(identities = ["energy_storage"], capabilities = [EnergyStorage])]
+#[derive(Debug)]
+pub struct EnergyStorageDevice;
+
+/// A block with an inventory, like a chest
+#[device(identities = ["item_handler"], capabilities = [ItemHandler])]
+#[derive(Debug)]
+pub struct ItemHandlerDevice;
+
+/// A block with fluid tanks, like a tank
+#[device(identities = ["fluid_handler"], capabilities = [FluidHandler])]
+#[derive(Debug)]
+pub struct FluidHandlerDevice;
+
+/// A block that stores items, fluids and energy all at once, like most machines.
+///
+/// Blocks with only some of these can be wrapped with the single capability devices instead, and moved between them with [RPCDevice::as_device].
+#[device(
+    identities = ["item_handler"],
+    requires = ["fluid_handler", "energy_storage"],
+    capabilities = [EnergyStorage, ItemHandler, FluidHandler]
+)]
+#[derive(Debug)]
+pub struct Machine;
+
+/// A device none of the wrappers know about, called by method name instead.
+#[derive(Clone, Debug)]
+pub struct DynamicDevice {
+    pub id: String,
+    /// The type names the device was listed with. Empty if it was made with [RPCDevice::from_id].
+    pub type_names: Vec<String>,
+}
+
+impl RPCDevice for DynamicDevice {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn from_id(id: String) -> Self {
+        DynamicDevice {
+            id,
+            type_names: Vec::new(),
+        }
+    }
+}
+
+impl From<DeviceData> for DynamicDevice {
+    fn from(device: DeviceData) -> DynamicDevice {
+        DynamicDevice {
+            id: device.device_id,
+            type_names: device.type_names,
+        }
+    }
+}
+
+impl DynamicDevice {
+    /// Whether the device was listed with this type name.
+    pub fn has(&self, type_name: &str) -> bool {
+        self.type_names.iter().any(|name| name == type_name)
+    }
+
+    /// Calls a method by name, with its arguments given as a tuple.
+    pub fn call<A: EncodeArgs, R: Deserialize>(
+        &self,
+        bus: &mut crate::DeviceBus,
+        method_name: &str,
+        args: A,
+    ) -> io::Result<R> {
+        let response = bus.call_with(|frame| {
+            let mut call = InvokeWriter::new(frame, method_name);
+            call.args(&args);
+            call.finish(&self.id);
+        })?;
+        Ok(response.data)
     }
+
+    /// Gets the descriptions of the device's methods.
+    pub fn methods<T: Deserialize>(&self, bus: &mut crate::DeviceBus) -> io::Result<T> {
+        Ok(bus.call(&Call::methods(&self.id))?.data)
+    }
+}
+
+macro_rules! known_devices {
+    ($($device:ident),* $(,)?) => {
+        /// Any device on the bus, wrapped as the first wrapper type that matches it. See [crate::DeviceBus::devices].
+        #[derive(Debug)]
+        pub enum KnownDevice {
+            $($device($device),)*
+            /// A device none of the wrappers match.
+            Unknown(DynamicDevice),
+        }
+
+        impl KnownDevice {
+            /// Picks a wrapper for a device from the device list, trying the ones with more capabilities first.
+            pub fn from_data(device: DeviceData) -> KnownDevice {
+                $(
+                    if $device::matches(&device) {
+                        return KnownDevice::$device($device::from_id(device.device_id));
+                    }
+                )*
+                KnownDevice::Unknown(device.into())
+            }
+
+            pub fn id(&self) -> &str {
+                match self {
+                    $(KnownDevice::$device(device) => device.id(),)*
+                    KnownDevice::Unknown(device) => device.id(),
+                }
+            }
+
+            /// Returns the name of the wrapper type, like `"RedstoneDevice"`.
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    $(KnownDevice::$device(_) => stringify!($device),)*
+                    KnownDevice::Unknown(_) => "Unknown",
+                }
+            }
+        }
+
+        impl From<DeviceData> for KnownDevice {
+            fn from(device: DeviceData) -> KnownDevice {
+                KnownDevice::from_data(device)
+            }
+        }
+    };
 }
 
-define_device!(
+// robots and machines also show up as item handlers and energy storage, so they go before those.
+known_devices!(
+    Robot,
+    Machine,
     RedstoneDevice,
-    "redstone",
-    "A device capable of interacting with redstone",
-    [RedstoneInterface]
-);
-define_device!(
     SoundCard,
-    "sound",
-    "A device capable of playing sounds",
-    [SoundInterface]
-);
-define_device!(
     FileImportExportCard,
-    "file_import_export",
-    "A device capable of importing and exporting files",
-    [FileImportExport]
-);
-define_device!(Robot, "robot", "A robit!", [RobotInterface]);
-define_device!(
     BlockOperationsModule,
-    "block_operations",
-    "A device capable of manipulating blocks in the world",
-    [BlockOperationsInterface]
-);
-define_device!(
     InventoryOperationsModule,
-    "inventory_operations",
-    "A device capable of manipulating inventories in the world",
-    [InventoryOperationsInterface]
+    EnergyStorageDevice,
+    ItemHandlerDevice,
+    FluidHandlerDevice,
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DeviceBus;
+
+    // a bus that answers every call with `reply`, along with the calls it got.
+    fn recording_bus(reply: &'static str) -> (DeviceBus, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        let bus = DeviceBus::serve(move |request| {
+            log.lock().unwrap().push(request.to_owned());
+            reply.to_owned()
+        })
+        .unwrap();
+
+        (bus, requests)
+    }
+
+    trait Overloads: RPCDevice {
+        #[rpc("get")]
+        fn get(a: Option<i32>, b: Option<i32>) -> i32;
+
+        #[rpc("get")]
+        fn get_at(at: i32, a: Option<i32>) -> i32;
+    }
+
+    impl Overloads for ItemHandlerDevice {}
+
+    #[test]
+    fn trailing_options_are_left_out_when_none() {
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":true}"#);
+        let module = BlockOperationsModule::from_id("m".to_owned());
+
+        assert!(module.excavate_at(&mut bus, None).unwrap());
+        assert!(module.place_at(&mut bus, Some(RobotSide::Up)).unwrap());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"excavate","parameters":[],"deviceId":"m"}}"#,
+                r#"{"type":"invoke","data":{"name":"place","parameters":["up"],"deviceId":"m"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn prepared_calls_follow_the_method_signature() {
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":true}"#);
+        let module = BlockOperationsModule::from_id("m".to_owned());
+
+        let excavate: crate::PreparedCall<(Option<RobotSide>,), bool> =
+            module.prepare_excavate_at();
+        assert!(excavate.call(&mut bus, (None::<RobotSide>,)).unwrap());
+        let place: crate::PreparedCall<(crate::ImplArg<dyn RobotSideArg>,), bool> =
+            module.prepare_place();
+        assert!(place.call(&mut bus, ("up",)).unwrap());
+        assert!(place.call(&mut bus, (RobotSide::Down,)).unwrap());
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"excavate","parameters":[],"deviceId":"m"}}"#,
+                r#"{"type":"invoke","data":{"name":"place","parameters":["up"],"deviceId":"m"}}"#,
+                r#"{"type":"invoke","data":{"name":"place","parameters":["down"],"deviceId":"m"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn prepared_void_methods_accept_void() {
+        let (mut bus, _) = recording_bus(r#"{"type":"result"}"#);
+        let card = FileImportExportCard::from_id("f".to_owned());
+
+        let begin: crate::PreparedCall<(&'static str,), ()> = card.prepare_begin_export_file();
+        let name = String::from("a.txt");
+        begin.call(&mut bus, (name.as_str(),)).unwrap();
+        assert!(card
+            .prepare_request_import_file()
+            .call(&mut bus, ())
+            .is_err());
+    }
+
+    #[test]
+    fn gaps_in_trailing_options_are_rejected() {
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":1}"#);
+        let device = ItemHandlerDevice::from_id("d".to_owned());
+
+        let err = device.get(&mut bus, None, Some(2)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(requests.lock().unwrap().is_empty());
+
+        assert_eq!(device.get(&mut bus, Some(1), None).unwrap(), 1);
+        assert_eq!(device.get(&mut bus, Some(1), Some(2)).unwrap(), 1);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"get","parameters":[1],"deviceId":"d"}}"#,
+                r#"{"type":"invoke","data":{"name":"get","parameters":[1,2],"deviceId":"d"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn required_parameters_are_always_sent() {
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":1}"#);
+        let device = ItemHandlerDevice::from_id("d".to_owned());
+
+        assert_eq!(device.get_at(&mut bus, 0, None).unwrap(), 1);
+        assert_eq!(device.get_at(&mut bus, 3, Some(4)).unwrap(), 1);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"get","parameters":[0],"deviceId":"d"}}"#,
+                r#"{"type":"invoke","data":{"name":"get","parameters":[3,4],"deviceId":"d"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_action_results_are_named_in_the_error() {
+        let (mut bus, _) = recording_bus(r#"{"type":"result","data":"CANCELLED"}"#);
+        let robot = Robot::from_id("r".to_owned());
+
+        assert_eq!(
+            robot.get_action_result(&mut bus, 1).unwrap(),
+            RobotActionResult::Unknown("CANCELLED".to_owned())
+        );
+        let err = robot.wait_for_action(&mut bus, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            r#"unknown robot action result "CANCELLED""#
+        );
+
+        // enums without a fallback still refuse strings they don't know.
+        assert!(miniserde_miku::json::from_str::<crate::types::Side>(r#""sideways""#).is_err());
+    }
+
+    #[test]
+    fn missing_action_results_are_told_apart() {
+        // a robot that has enqueued actions 1 to 5, and kept none of their results.
+        let mut bus = DeviceBus::serve(|request| {
+            let data = if request.contains(r#""name":"getLastActionId""#) {
+                "5"
+            } else {
+                "null"
+            };
+            format!(r#"{{"type":"result","data":{}}}"#, data)
+        })
+        .unwrap();
+        let robot = Robot::from_id("r".to_owned());
+        let policy = WaitPolicy::new();
+
+        for (action, outcome) in [
+            (1, ActionOutcome::Expired { action: 1 }),
+            (5, ActionOutcome::Expired { action: 5 }),
+            (6, ActionOutcome::UnknownAction { action: 6 }),
+        ] {
+            assert_eq!(
+                robot
+                    .poll_action(&mut bus, action, &mut policy.start())
+                    .unwrap(),
+                outcome
+            );
+        }
+        let err = robot.wait_for_action(&mut bus, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "the result of action 3 has expired");
+    }
+
+    // a device listed under either of two names, with a field of its own.
+    #[device(identities = ["energy_storage", "battery"], capabilities = [EnergyStorage])]
+    #[derive(Debug)]
+    struct Battery {
+        label: Option<String>,
+    }
+
+    fn listed(id: &str, type_names: &[&str]) -> DeviceData {
+        DeviceData {
+            device_id: id.to_owned(),
+            type_names: type_names.iter().map(|&name| name.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn devices_match_any_of_their_identities() {
+        assert_eq!(Battery::IDENTITY, "energy_storage");
+        assert!(Battery::matches(&listed("a", &["battery"])));
+        assert!(Battery::matches(&listed(
+            "a",
+            &["item_handler", "energy_storage"]
+        )));
+        assert!(!Battery::matches(&listed("a", &["item_handler"])));
+
+        let (mut bus, _) = recording_bus(
+            r#"{"type":"list","data":[{"deviceId":"a","typeNames":["redstone"]},{"deviceId":"b","typeNames":["battery"]}]}"#,
+        );
+        let battery: Battery = bus.wrap().unwrap().unwrap();
+        assert_eq!(battery.id(), "b");
+        assert_eq!(battery.label, None);
+
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":5}"#);
+        assert_eq!(battery.get_energy_stored(&mut bus).unwrap(), 5);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                r#"{"type":"invoke","data":{"name":"getEnergyStored","parameters":[],"deviceId":"b"}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn known_devices_pick_the_most_specific_wrapper() {
+        let known = |type_names: &[&str]| KnownDevice::from_data(listed("d", type_names));
+
+        // robots are item handlers and energy storage too.
+        let robot = known(&["energy_storage", "item_handler", "robot"]);
+        assert!(matches!(robot, KnownDevice::Robot(_)));
+        assert_eq!(robot.kind(), "Robot");
+        assert_eq!(robot.id(), "d");
+
+        assert_eq!(
+            known(&["item_handler", "fluid_handler", "energy_storage"]).kind(),
+            "Machine"
+        );
+        assert_eq!(
+            known(&["item_handler", "energy_storage"]).kind(),
+            "EnergyStorageDevice"
+        );
+        assert_eq!(
+            known(&["item_handler", "redstone"]).kind(),
+            "RedstoneDevice"
+        );
+
+        let KnownDevice::Unknown(device) = known(&["computer", "screen"]) else {
+            panic!("a computer has no wrapper");
+        };
+        assert_eq!(device.id, "d");
+        assert!(device.has("screen") && !device.has("robot"));
+    }
+
+    #[test]
+    fn frames_are_cached_per_device() {
+        let (mut bus, requests) = recording_bus(r#"{"type":"result","data":1}"#);
+        let a = EnergyStorageDevice::from_id("a".to_owned());
+        let b = EnergyStorageDevice::from_id("b".to_owned());
+
+        a.get_energy_stored(&mut bus).unwrap();
+        a.get_energy_stored(&mut bus).unwrap();
+        b.get_energy_stored(&mut bus).unwrap();
+        a.get_max_energy_stored(&mut bus).unwrap();
+        assert_eq!(
+            format!("{:?}", a.frame_cache().unwrap()),
+            "FrameCache { frames: 2 }"
+        );
+        assert_eq!(
+            format!("{:?}", b.frame_cache().unwrap()),
+            "FrameCache { frames: 1 }"
+        );
+
+        // cached frames are the same ones that would have been rendered.
+        let frame = |name, id| {
+            format!(
+                r#"{{"type":"invoke","data":{{"name":"{}","parameters":[],"deviceId":"{}"}}}}"#,
+                name, id
+            )
+        };
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                frame("getEnergyStored", "a"),
+                frame("getEnergyStored", "a"),
+                frame("getEnergyStored", "b"),
+                frame("getMaxEnergyStored", "a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_caches_render_once_per_method() {
+        let cache = FrameCache::new();
+        let mut renders = 0;
+        let mut frame = |method_name| {
+            cache.frame(method_name, |out| {
+                renders += 1;
+                out.extend_from_slice(method_name.as_bytes());
+            })
+        };
+
+        assert_eq!(&*frame("a"), b"a");
+        assert_eq!(&*frame("a"), b"a");
+        assert_eq!(&*frame("b"), b"b");
+        assert_eq!(renders, 2);
+
+        cache.clear();
+        assert_eq!(format!("{:?}", cache), "FrameCache { frames: 0 }");
+    }
+
+    #[test]
+    fn wrappers_can_be_shared_across_threads() {
+        fn assert_sync<T: Send + Sync>(_: &T) {}
+
+        let device =
+            EnergyStorageDevice::from_id("5c3c8d36-2b0e-4d1e-8e5b-6f8f3b7a9c21".to_owned());
+        assert_sync(&device);
+        assert_sync(device.frame_cache().unwrap());
+    }
+}
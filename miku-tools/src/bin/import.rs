@@ -68,8 +68,9 @@ fn main() -> io::Result<()> {
         let mut last_printed_percent: usize = 0;
 
         let start = Instant::now();
-        while let Some(ref bytes) = card.read_import_file(&mut bus)? {
-            out.write_all(bytes)?;
+        let mut bytes = Vec::new();
+        while card.read_import_file_into(&mut bus, &mut bytes)? {
+            out.write_all(&bytes)?;
 
             offset += bytes.len();
             let pct = offset * 100 / info.size as usize;